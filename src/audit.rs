@@ -0,0 +1,43 @@
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+use crate::RbacChange;
+
+/// An entry of the audit log enabled with `InMemoryRbac::enable_audit`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuditEntry<UId, RId, PId> {
+    /// When the change was made.
+    pub timestamp: SystemTime,
+    /// What has changed.
+    pub action: RbacChange<UId, RId, PId>,
+}
+
+/// A ring buffer of the most recent audit entries.
+#[derive(Clone)]
+pub(crate) struct AuditLog<UId, RId, PId> {
+    pub(crate) entries: VecDeque<AuditEntry<UId, RId, PId>>,
+    capacity: usize,
+}
+
+impl<UId, RId, PId> AuditLog<UId, RId, PId> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        AuditLog {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub(crate) fn push(&mut self, action: RbacChange<UId, RId, PId>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(AuditEntry {
+            timestamp: SystemTime::now(),
+            action,
+        });
+    }
+}
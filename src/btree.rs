@@ -0,0 +1,142 @@
+use std::collections::{btree_set, BTreeMap, BTreeSet};
+use std::iter::Cloned;
+
+use crate::traits::{Identifiable, RbacIterators, RbacModel};
+use crate::InMemoryRbacError;
+
+/// A model backed by `BTreeMap`s and `BTreeSet`s, so that its iterators yield ids in ascending
+/// order, e.g. for deterministic output in snapshot tests.
+///
+/// Only role assignments and permissions are supported, without any of the hierarchies,
+/// constraints or other extensions of `InMemoryRbac`.
+pub struct BTreeRbac<U: Identifiable, R: Identifiable, P: Identifiable>
+where
+    U::Id: Ord,
+    R::Id: Ord,
+    P::Id: Ord,
+{
+    user_role_map: BTreeMap<U::Id, BTreeSet<R::Id>>,
+    role_permission_map: BTreeMap<R::Id, BTreeSet<P::Id>>,
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable> BTreeRbac<U, R, P>
+where
+    U::Id: Ord,
+    R::Id: Ord,
+    P::Id: Ord,
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        BTreeRbac {
+            user_role_map: BTreeMap::new(),
+            role_permission_map: BTreeMap::new(),
+        }
+    }
+
+    fn role_has_permission_id(&self, role: &R::Id, permission: &P::Id) -> bool {
+        match self.role_permission_map.get(role) {
+            Some(permissions) => permissions.contains(permission),
+            None => false,
+        }
+    }
+}
+
+impl<'a, U: Identifiable, R: Identifiable, P: Identifiable> RbacIterators<U, R, P>
+    for &'a BTreeRbac<U, R, P>
+where
+    U::Id: Ord,
+    R::Id: Ord + Clone,
+    P::Id: Ord + Clone,
+{
+    type UserRoles = Cloned<btree_set::Iter<'a, R::Id>>;
+    type RolePermissions = Cloned<btree_set::Iter<'a, P::Id>>;
+    type Error = InMemoryRbacError;
+
+    fn iter_user_role_ids(self, user: &U) -> Result<Self::UserRoles, Self::Error> {
+        match self.user_role_map.get(&user.get_rbac_id()) {
+            Some(roles) => Ok(roles.iter().cloned()),
+            None => Err(InMemoryRbacError::UserHasNoRoles),
+        }
+    }
+
+    fn iter_role_permission_ids(self, role: &R) -> Result<Self::RolePermissions, Self::Error> {
+        match self.role_permission_map.get(&role.get_rbac_id()) {
+            Some(permissions) => Ok(permissions.iter().cloned()),
+            None => Err(InMemoryRbacError::RoleHasNoPermissions),
+        }
+    }
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable> RbacModel<U, R, P> for BTreeRbac<U, R, P>
+where
+    U::Id: Ord,
+    R::Id: Ord + Clone,
+    P::Id: Ord + Clone,
+{
+    type Error = InMemoryRbacError;
+
+    fn assign_role(&mut self, user: &U, role: &R) -> Result<bool, Self::Error> {
+        Ok(self
+            .user_role_map
+            .entry(user.get_rbac_id())
+            .or_default()
+            .insert(role.get_rbac_id()))
+    }
+
+    fn unassign_role(&mut self, user: &U, role: &R) -> Result<bool, Self::Error> {
+        let user = user.get_rbac_id();
+        match self.user_role_map.get_mut(&user) {
+            Some(roles) => {
+                let was_present = roles.remove(&role.get_rbac_id());
+                if roles.is_empty() {
+                    self.user_role_map.remove(&user);
+                }
+                Ok(was_present)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn add_permission(&mut self, role: &R, permission: &P) -> Result<bool, Self::Error> {
+        Ok(self
+            .role_permission_map
+            .entry(role.get_rbac_id())
+            .or_default()
+            .insert(permission.get_rbac_id()))
+    }
+
+    fn remove_permission(&mut self, role: &R, permission: &P) -> Result<bool, Self::Error> {
+        let role = role.get_rbac_id();
+        match self.role_permission_map.get_mut(&role) {
+            Some(permissions) => {
+                let was_present = permissions.remove(&permission.get_rbac_id());
+                if permissions.is_empty() {
+                    self.role_permission_map.remove(&role);
+                }
+                Ok(was_present)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn user_has_role(&self, user: &U, role: &R) -> Result<bool, Self::Error> {
+        Ok(match self.user_role_map.get(&user.get_rbac_id()) {
+            Some(roles) => roles.contains(&role.get_rbac_id()),
+            None => false,
+        })
+    }
+
+    fn role_has_permission(&self, role: &R, permission: &P) -> Result<bool, Self::Error> {
+        Ok(self.role_has_permission_id(&role.get_rbac_id(), &permission.get_rbac_id()))
+    }
+
+    fn user_has_permission(&self, user: &U, permission: &P) -> Result<bool, Self::Error> {
+        let permission = permission.get_rbac_id();
+        Ok(self
+            .user_role_map
+            .get(&user.get_rbac_id())
+            .into_iter()
+            .flatten()
+            .any(|role| self.role_has_permission_id(role, &permission)))
+    }
+}
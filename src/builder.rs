@@ -0,0 +1,85 @@
+use std::hash::Hash;
+
+use crate::traits::Identifiable;
+use crate::{ConstraintViolation, InMemoryRbac};
+
+/// Builds an `InMemoryRbac` from declared role assignments, permission grants and constraints.
+pub struct RbacBuilder<U: Identifiable, R: Identifiable, P: Identifiable>
+where
+    U::Id: Eq + Hash,
+    R::Id: Eq + Hash,
+    P::Id: Eq + Hash,
+{
+    model: InMemoryRbac<U, R, P>,
+    assignments: Vec<(U::Id, R::Id)>,
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable> RbacBuilder<U, R, P>
+where
+    U::Id: Eq + Hash + Clone,
+    R::Id: Eq + Hash + Clone,
+    P::Id: Eq + Hash + Clone,
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        RbacBuilder {
+            model: InMemoryRbac::new(),
+            assignments: Vec::new(),
+        }
+    }
+
+    /// Declares that the user has the role.
+    pub fn assign(mut self, user: &U, role: &R) -> Self {
+        self.assignments
+            .push((user.get_rbac_id(), role.get_rbac_id()));
+        self
+    }
+
+    /// Declares that the role has the permission.
+    pub fn grant(mut self, role: &R, permission: &P) -> Self {
+        self.model
+            .role_permisson_map
+            .entry(role.get_rbac_id())
+            .or_default()
+            .insert(permission.get_rbac_id());
+        self
+    }
+
+    /// Declares that no user may hold both roles, like `InMemoryRbac::add_mutually_exclusive_roles`.
+    pub fn mutually_exclusive_roles(mut self, a: &R, b: &R) -> Self {
+        self.model.add_mutually_exclusive_roles(a, b);
+        self
+    }
+
+    /// Builds the model, or returns every constraint violated by the assignments.
+    ///
+    /// The assignments are checked in the order they were declared, after all grants.
+    pub fn try_build(self) -> Result<InMemoryRbac<U, R, P>, Vec<ConstraintViolation>> {
+        let mut model = self.model;
+        let mut violations = Vec::new();
+        for (user, role) in self.assignments {
+            match model.check_assign_id(&user, &role) {
+                Ok(()) => {
+                    model.user_role_map.entry(user).or_default().insert(role);
+                }
+                Err(violation) => violations.push(violation),
+            }
+        }
+        if violations.is_empty() {
+            Ok(model)
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Builds the model like `try_build`.
+    ///
+    /// # Panics
+    /// Panics if any assignment violates a constraint.
+    pub fn build(self) -> InMemoryRbac<U, R, P> {
+        match self.try_build() {
+            Ok(model) => model,
+            Err(violations) => panic!("assignments violate constraints: {:?}", violations),
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// A cache of permission checks owned by the caller, used with
+/// `InMemoryRbac::user_has_permission_cached`.
+///
+/// The cache doesn't notice changes to the model, so it's meant to be short-lived, e.g. scoped to
+/// a single request. Discarding or clearing it after the model has changed is the
+/// responsibility of the caller.
+#[derive(Debug, Clone)]
+pub struct CheckCache<UId: Eq + Hash, PId: Eq + Hash> {
+    pub(crate) results: HashMap<(UId, PId), bool>,
+}
+
+impl<UId: Eq + Hash, PId: Eq + Hash> CheckCache<UId, PId> {
+    pub fn new() -> Self {
+        CheckCache {
+            results: HashMap::new(),
+        }
+    }
+
+    /// Forgets all cached results.
+    pub fn clear(&mut self) {
+        self.results.clear();
+    }
+
+    /// Returns the number of cached results.
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Returns `true` if no results are cached.
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+}
+
+impl<UId: Eq + Hash, PId: Eq + Hash> Default for CheckCache<UId, PId> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,45 @@
+use std::time::SystemTime;
+
+/// A single change to a model.
+///
+/// Changes are recorded in the audit log and can be applied to a model with
+/// `InMemoryRbac::apply_change`, e.g. to keep a replica in sync with a change feed.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RbacChange<UId, RId, PId> {
+    /// Assigns a role to a user.
+    AssignRole { user: UId, role: RId },
+    /// Unassigns a role from a user.
+    UnassignRole { user: UId, role: RId },
+    /// Adds a permission to a role.
+    AddPermission { role: RId, permission: PId },
+    /// Removes a permission from a role.
+    RemovePermission { role: RId, permission: PId },
+    /// Grants a user all permissions until the given time.
+    EmergencyOverride { user: UId, until: SystemTime },
+    /// Assigns a role to a user until the given time.
+    AssignRoleUntil {
+        user: UId,
+        role: RId,
+        until: SystemTime,
+    },
+    /// Denies a permission to a user, even if a role grants it.
+    DenyPermission { user: UId, permission: PId },
+    /// Lifts the denial of a permission to a user.
+    AllowPermission { user: UId, permission: PId },
+}
+
+impl<UId, RId, PId> RbacChange<UId, RId, PId> {
+    /// Returns the user affected by the change, if any.
+    pub fn user(&self) -> Option<&UId> {
+        match self {
+            RbacChange::AssignRole { user, .. }
+            | RbacChange::UnassignRole { user, .. }
+            | RbacChange::EmergencyOverride { user, .. }
+            | RbacChange::AssignRoleUntil { user, .. }
+            | RbacChange::DenyPermission { user, .. }
+            | RbacChange::AllowPermission { user, .. } => Some(user),
+            RbacChange::AddPermission { .. } | RbacChange::RemovePermission { .. } => None,
+        }
+    }
+}
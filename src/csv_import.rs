@@ -0,0 +1,103 @@
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::io::{Read, Write};
+
+use crate::traits::{Identifiable, RbacModel};
+use crate::InMemoryRbac;
+
+/// The input of `InMemoryRbac::from_csv` that an error occurred in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvInput {
+    /// The `user,role` assignments.
+    Assignments,
+    /// The `role,permission` grants.
+    Grants,
+}
+
+/// The error returned by `InMemoryRbac::from_csv` if a row can't be read.
+#[derive(Debug)]
+pub struct CsvImportError {
+    /// The input containing the row.
+    pub input: CsvInput,
+    /// The 1-based number of the row, not counting the header.
+    pub row: usize,
+    /// The underlying error.
+    pub error: csv::Error,
+}
+
+impl fmt::Display for CsvImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "row {} of the {:?}: {}",
+            self.row, self.input, self.error
+        )
+    }
+}
+
+impl Error for CsvImportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+fn read_pairs<Rd: Read>(
+    input: Rd,
+    kind: CsvInput,
+) -> impl Iterator<Item = Result<(String, String), CsvImportError>> {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(input)
+        .into_deserialize()
+        .enumerate()
+        .map(move |(index, pair)| {
+            pair.map_err(|error| CsvImportError {
+                input: kind,
+                row: index + 1,
+                error,
+            })
+        })
+}
+
+impl InMemoryRbac<String, String, String> {
+    /// Reads a model from two CSV streams with headers, one of `user,role` assignments and one
+    /// of `role,permission` grants, e.g. exported from a spreadsheet.
+    ///
+    /// Fails on the first row that doesn't have exactly two columns.
+    pub fn from_csv<Rd: Read>(assignments: Rd, grants: Rd) -> Result<Self, CsvImportError> {
+        let mut model = InMemoryRbac::new();
+        for pair in read_pairs(assignments, CsvInput::Assignments) {
+            let (user, role) = pair?;
+            model
+                .assign_role(&user, &role)
+                .expect("a new model has no constraints");
+        }
+        for pair in read_pairs(grants, CsvInput::Grants) {
+            let (role, permission) = pair?;
+            model
+                .add_permission(&role, &permission)
+                .expect("a new model has no permission limit");
+        }
+        Ok(model)
+    }
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable, S: BuildHasher + Clone>
+    InMemoryRbac<U, R, P, S>
+where
+    U::Id: Eq + Hash + Clone + fmt::Display,
+    R::Id: Eq + Hash + Clone + fmt::Display,
+    P::Id: Eq + Hash + Clone + fmt::Display,
+{
+    /// Writes the rows of `export_matrix` as CSV with a `user,role,permission` header.
+    pub fn to_csv<W: Write>(&self, writer: W) -> Result<(), csv::Error> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(["user", "role", "permission"])?;
+        for (user, role, permission) in self.export_matrix() {
+            writer.write_record([user.to_string(), role.to_string(), permission.to_string()])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
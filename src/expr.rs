@@ -0,0 +1,24 @@
+/// A boolean expression over permissions, evaluated with `RbacModel::evaluate`.
+///
+/// # Examples
+/// Requiring `edit` but not `readonly`:
+///
+/// ```
+/// use rbac::PermExpr;
+///
+/// let expr = PermExpr::And(vec![
+///     PermExpr::Has("edit"),
+///     PermExpr::Not(Box::new(PermExpr::Has("readonly"))),
+/// ]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum PermExpr<P> {
+    /// True if every sub-expression is true, or if there are none.
+    And(Vec<PermExpr<P>>),
+    /// True if at least one sub-expression is true.
+    Or(Vec<PermExpr<P>>),
+    /// Negates the sub-expression.
+    Not(Box<PermExpr<P>>),
+    /// True if the user has the permission.
+    Has(P),
+}
@@ -0,0 +1,65 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use crate::traits::{Identifiable, RbacIterators, RbacModel};
+use crate::{InMemoryRbac, InMemoryRbacError};
+
+/// An immutable snapshot of an `InMemoryRbac`, created by `InMemoryRbac::arc_snapshot`.
+///
+/// A `FrozenRbac` is usually held in an `Arc` that request handlers can cheaply clone and keep
+/// around without any locking. To apply updates, create a new snapshot and swap it in, e.g. with
+/// `RbacHandle` from the `arc-swap` feature. Holders of the old snapshot keep seeing the old state.
+pub struct FrozenRbac<U: Identifiable, R: Identifiable, P: Identifiable, S = RandomState>
+where
+    U::Id: Eq + Hash,
+    R::Id: Eq + Hash,
+    P::Id: Eq + Hash,
+{
+    pub(crate) model: InMemoryRbac<U, R, P, S>,
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable, S: BuildHasher + Clone>
+    FrozenRbac<U, R, P, S>
+where
+    U::Id: Eq + Hash + Clone,
+    R::Id: Eq + Hash + Clone,
+    P::Id: Eq + Hash + Clone,
+{
+    /// Checks whether the user has the role in this snapshot.
+    pub fn user_has_role(&self, user: &U, role: &R) -> Result<bool, InMemoryRbacError> {
+        self.model.user_has_role(user, role)
+    }
+
+    /// Checks whether the role has the permission in this snapshot.
+    pub fn role_has_permission(&self, role: &R, permission: &P) -> Result<bool, InMemoryRbacError> {
+        self.model.role_has_permission(role, permission)
+    }
+
+    /// Checks whether the user has the permission in this snapshot.
+    pub fn user_has_permission(&self, user: &U, permission: &P) -> Result<bool, InMemoryRbacError> {
+        self.model.user_has_permission(user, permission)
+    }
+}
+
+impl<'a, U, R, P, S: BuildHasher> RbacIterators<U, R, P> for &'a FrozenRbac<U, R, P, S>
+where
+    U: Identifiable,
+    U::Id: Eq + Hash,
+    R: Identifiable,
+    R::Id: Eq + Hash + Clone,
+    P: Identifiable,
+    P::Id: Eq + Hash + Clone,
+{
+    type UserRoles = <&'a InMemoryRbac<U, R, P, S> as RbacIterators<U, R, P>>::UserRoles;
+    type RolePermissions =
+        <&'a InMemoryRbac<U, R, P, S> as RbacIterators<U, R, P>>::RolePermissions;
+    type Error = InMemoryRbacError;
+
+    fn iter_user_role_ids(self, user: &U) -> Result<Self::UserRoles, Self::Error> {
+        self.model.iter_user_role_ids(user)
+    }
+
+    fn iter_role_permission_ids(self, role: &R) -> Result<Self::RolePermissions, Self::Error> {
+        self.model.iter_role_permission_ids(role)
+    }
+}
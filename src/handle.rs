@@ -0,0 +1,49 @@
+use std::hash::Hash;
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+
+use crate::traits::Identifiable;
+use crate::{FrozenRbac, InMemoryRbac};
+
+/// A shared handle to the active snapshot of a model that can be replaced atomically.
+///
+/// Readers call `load` to get the current snapshot without ever blocking, while a background
+/// task calls `reload` to swap in a new model, e.g. after loading it from a database. A reader
+/// always sees either the old or the new model, never a mix of both.
+pub struct RbacHandle<U: Identifiable, R: Identifiable, P: Identifiable>
+where
+    U::Id: Eq + Hash,
+    R::Id: Eq + Hash,
+    P::Id: Eq + Hash,
+{
+    active: ArcSwap<FrozenRbac<U, R, P>>,
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable> RbacHandle<U, R, P>
+where
+    U::Id: Eq + Hash,
+    R::Id: Eq + Hash,
+    P::Id: Eq + Hash,
+{
+    /// Creates a handle with `model` as the active snapshot.
+    pub fn new(model: InMemoryRbac<U, R, P>) -> Self {
+        RbacHandle {
+            active: ArcSwap::from_pointee(FrozenRbac { model }),
+        }
+    }
+
+    /// Returns the active snapshot.
+    ///
+    /// The snapshot stays valid and unchanged for as long as it is held, even across reloads.
+    pub fn load(&self) -> Arc<FrozenRbac<U, R, P>> {
+        self.active.load_full()
+    }
+
+    /// Builds a new model with `f` and makes it the active snapshot.
+    ///
+    /// Readers keep seeing the previous snapshot until `f` returns.
+    pub fn reload<F: Fn() -> InMemoryRbac<U, R, P>>(&self, f: F) {
+        self.active.store(Arc::new(FrozenRbac { model: f() }));
+    }
+}
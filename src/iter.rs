@@ -0,0 +1,75 @@
+use std::collections::{hash_set, HashMap};
+use std::hash::Hash;
+use std::time::SystemTime;
+
+/// An iterator over the ids of the roles of a user whose assignment hasn't expired, created by
+/// `RbacIterators::iter_user_role_ids` of `InMemoryRbac`.
+///
+/// It borrows the roles from the model instead of collecting them.
+#[derive(Debug, Clone)]
+pub struct UserRoleIds<'a, RId> {
+    pub(crate) roles: hash_set::Iter<'a, RId>,
+    pub(crate) expiries: Option<&'a HashMap<RId, SystemTime>>,
+    pub(crate) now: SystemTime,
+}
+
+impl<'a, RId: Eq + Hash + Clone> Iterator for UserRoleIds<'a, RId> {
+    type Item = RId;
+
+    fn next(&mut self) -> Option<RId> {
+        let expiries = self.expiries;
+        let now = self.now;
+        self.roles
+            .find(
+                |role| match expiries.and_then(|expiries| expiries.get(*role)) {
+                    Some(until) => now < *until,
+                    None => true,
+                },
+            )
+            .cloned()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.expiries {
+            Some(_) => (0, self.roles.size_hint().1),
+            None => self.roles.size_hint(),
+        }
+    }
+}
+
+/// An iterator over the ids of the permissions of a role, including inherited ones, created by
+/// `RbacIterators::iter_role_permission_ids` of `InMemoryRbac`.
+///
+/// It borrows the permissions from the model. The permissions are only collected first if the
+/// role has parents or the model has permission groups and `materialize_inheritance` hasn't
+/// been called since the last change.
+#[derive(Debug)]
+pub struct RolePermissionIds<'a, PId> {
+    pub(crate) inner: RolePermissionsInner<'a, PId>,
+}
+
+#[derive(Debug)]
+pub(crate) enum RolePermissionsInner<'a, PId> {
+    Direct(hash_set::Iter<'a, PId>),
+    Resolved(hash_set::IntoIter<&'a PId>),
+}
+
+impl<'a, PId: Clone> Iterator for RolePermissionIds<'a, PId> {
+    type Item = PId;
+
+    fn next(&mut self) -> Option<PId> {
+        match &mut self.inner {
+            RolePermissionsInner::Direct(permissions) => permissions.next().cloned(),
+            RolePermissionsInner::Resolved(permissions) => permissions.next().cloned(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.inner {
+            RolePermissionsInner::Direct(permissions) => permissions.size_hint(),
+            RolePermissionsInner::Resolved(permissions) => permissions.size_hint(),
+        }
+    }
+}
+
+impl<'a, PId: Clone> ExactSizeIterator for RolePermissionIds<'a, PId> {}
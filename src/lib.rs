@@ -1,40 +1,2926 @@
 // #![warn(missing_docs)]
 //! A crate providing role based access control.
 
+mod audit;
+mod btree;
+mod builder;
+mod cache;
+mod change;
+#[cfg(feature = "csv")]
+mod csv_import;
+mod expr;
+mod frozen;
+#[cfg(feature = "arc-swap")]
+mod handle;
+mod iter;
+mod macros;
+mod observer;
+#[cfg(feature = "openfga")]
+mod openfga;
+#[cfg(feature = "phf")]
+mod phf;
+#[cfg(feature = "serde")]
+mod serialize;
+mod shared;
+mod tenant;
 pub mod traits;
+#[cfg(feature = "json")]
+mod versioned;
 
-use std::collections::hash_map::Entry;
-use std::collections::{HashMap, HashSet};
-use std::hash::Hash;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::hash_map::{Entry, RandomState};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::Arc;
+use std::time::SystemTime;
 
-use traits::{Identifiable, RbacIterators, RbacModel};
+pub use audit::AuditEntry;
+pub use btree::BTreeRbac;
+pub use builder::RbacBuilder;
+pub use cache::CheckCache;
+pub use change::RbacChange;
+#[cfg(feature = "csv")]
+pub use csv_import::{CsvImportError, CsvInput};
+pub use expr::PermExpr;
+pub use frozen::FrozenRbac;
+#[cfg(feature = "arc-swap")]
+pub use handle::RbacHandle;
+pub use iter::{RolePermissionIds, UserRoleIds};
+pub use observer::{ObservedRbac, RbacObserver};
+#[cfg(feature = "openfga")]
+pub use openfga::FgaTuple;
+#[cfg(feature = "phf")]
+pub use phf::PhfRbac;
+pub use shared::SharedRbac;
+pub use tenant::MultiTenantRbac;
+#[cfg(feature = "json")]
+pub use versioned::{VersionedJsonError, MODEL_VERSION};
 
-pub struct InMemoryRbac<U: Identifiable, R: Identifiable, P: Identifiable>
+use audit::AuditLog;
+use iter::RolePermissionsInner;
+use traits::{AsPermission, Identifiable, NotFound, RbacIterators, RbacModel};
+
+pub struct InMemoryRbac<U: Identifiable, R: Identifiable, P: Identifiable, S = RandomState>
+where
+    U::Id: Eq + Hash,
+    R::Id: Eq + Hash,
+    P::Id: Eq + Hash,
+{
+    user_role_map: HashMap<U::Id, HashSet<R::Id>, S>,
+    role_permisson_map: HashMap<R::Id, HashSet<P::Id>, S>,
+    max_permissions_per_role: Option<usize>,
+    assigned_by_map: HashMap<U::Id, HashMap<R::Id, U::Id>>,
+    idempotency_keys: IdempotencyKeys,
+    prerequisite_map: HashMap<P::Id, P::Id>,
+    min_roles_map: HashMap<P::Id, usize>,
+    audit_log: Option<AuditLog<U::Id, R::Id, P::Id>>,
+    emergency_overrides: HashMap<U::Id, SystemTime>,
+    permission_meta: HashMap<P::Id, PermissionMeta>,
+    exclusive_roles_map: HashMap<R::Id, HashSet<R::Id>>,
+    max_users_per_role_map: HashMap<R::Id, usize>,
+    strict_roles: bool,
+    role_prerequisite_map: HashMap<R::Id, HashSet<R::Id>>,
+    role_parent_map: HashMap<R::Id, HashSet<R::Id>>,
+    role_expiry_map: HashMap<U::Id, HashMap<R::Id, SystemTime>>,
+    user_denied_map: HashMap<U::Id, HashSet<P::Id>>,
+    permission_group_map: HashMap<P::Id, HashSet<P::Id>>,
+    max_resolution_depth: usize,
+    materialized_map: Option<HashMap<R::Id, HashSet<P::Id>>>,
+    clock: fn() -> SystemTime,
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable> InMemoryRbac<U, R, P>
+where
+    U::Id: Eq + Hash,
+    R::Id: Eq + Hash,
+    P::Id: Eq + Hash,
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+
+    /// Creates an empty model with space for at least `users` users with roles and `roles` roles
+    /// with permissions before reallocating.
+    pub fn with_capacity(users: usize, roles: usize) -> Self {
+        Self::with_capacity_and_hasher(users, roles, RandomState::new())
+    }
+
+    /// Creates a model from pairs of users and their roles, and pairs of roles and their
+    /// permissions.
+    pub fn from_entities<'a, A, G>(assignments: A, grants: G) -> Self
+    where
+        A: IntoIterator<Item = (&'a U, &'a R)>,
+        G: IntoIterator<Item = (&'a R, &'a P)>,
+        U: 'a,
+        R: 'a,
+        P: 'a,
+    {
+        Self::from_assignments(
+            assignments
+                .into_iter()
+                .map(|(user, role)| (user.get_rbac_id(), role.get_rbac_id())),
+            grants
+                .into_iter()
+                .map(|(role, permission)| (role.get_rbac_id(), permission.get_rbac_id())),
+        )
+    }
+
+    /// Creates a model like `from_entities`, but from pairs of ids.
+    pub fn from_assignments<A, G>(assignments: A, grants: G) -> Self
+    where
+        A: IntoIterator<Item = (U::Id, R::Id)>,
+        G: IntoIterator<Item = (R::Id, P::Id)>,
+    {
+        let mut model = Self::new();
+        for (user, role) in assignments {
+            model.user_role_map.entry(user).or_default().insert(role);
+        }
+        for (role, permission) in grants {
+            model
+                .role_permisson_map
+                .entry(role)
+                .or_default()
+                .insert(permission);
+        }
+        model
+    }
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable, S: BuildHasher + Clone>
+    InMemoryRbac<U, R, P, S>
+where
+    U::Id: Eq + Hash,
+    R::Id: Eq + Hash,
+    P::Id: Eq + Hash,
+{
+    /// Creates an empty model that uses `hasher` to hash the ids of users and roles, e.g. to
+    /// use a faster hash function than the default one.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self::with_capacity_and_hasher(0, 0, hasher)
+    }
+
+    /// Creates an empty model like `with_capacity` that uses `hasher` like `with_hasher`.
+    pub fn with_capacity_and_hasher(users: usize, roles: usize, hasher: S) -> Self {
+        InMemoryRbac {
+            user_role_map: HashMap::with_capacity_and_hasher(users, hasher.clone()),
+            role_permisson_map: HashMap::with_capacity_and_hasher(roles, hasher),
+            max_permissions_per_role: None,
+            assigned_by_map: HashMap::new(),
+            idempotency_keys: IdempotencyKeys::new(),
+            prerequisite_map: HashMap::new(),
+            min_roles_map: HashMap::new(),
+            audit_log: None,
+            emergency_overrides: HashMap::new(),
+            permission_meta: HashMap::new(),
+            exclusive_roles_map: HashMap::new(),
+            max_users_per_role_map: HashMap::new(),
+            strict_roles: false,
+            role_prerequisite_map: HashMap::new(),
+            role_parent_map: HashMap::new(),
+            role_expiry_map: HashMap::new(),
+            user_denied_map: HashMap::new(),
+            permission_group_map: HashMap::new(),
+            max_resolution_depth: DEFAULT_MAX_RESOLUTION_DEPTH,
+            materialized_map: None,
+            clock: SystemTime::now,
+        }
+    }
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable, S: BuildHasher> InMemoryRbac<U, R, P, S>
+where
+    U::Id: Eq + Hash,
+    R::Id: Eq + Hash,
+    P::Id: Eq + Hash,
+{
+    /// Returns the number of users with at least one role.
+    pub fn user_count(&self) -> usize {
+        self.user_role_map.len()
+    }
+
+    /// Returns the number of roles with at least one permission.
+    pub fn role_count(&self) -> usize {
+        self.role_permisson_map.len()
+    }
+
+    /// Returns the number of role assignments over all users.
+    pub fn total_assignments(&self) -> usize {
+        self.user_role_map.values().map(HashSet::len).sum()
+    }
+
+    /// Returns `true` if there are no role assignments and no roles with permissions.
+    ///
+    /// Settings and rules like limits, constraints and role parents aren't considered.
+    pub fn is_empty(&self) -> bool {
+        self.user_role_map.is_empty() && self.role_permisson_map.is_empty()
+    }
+
+    /// Checks whether the model knows the user.
+    ///
+    /// There is no separate list of users, so a user is only known while they have at least one
+    /// role, even if that assignment has expired.
+    pub fn contains_user(&self, user: &U) -> bool {
+        self.user_role_map.contains_key(&user.get_rbac_id())
+    }
+
+    /// Checks whether the model knows the role, i.e. whether it has at least one permission of
+    /// its own.
+    ///
+    /// Roles that only inherit permissions from a parent aren't considered defined.
+    pub fn contains_role_definition(&self, role: &R) -> bool {
+        self.role_permisson_map.contains_key(&role.get_rbac_id())
+    }
+
+    /// Returns the number of users with roles and the number of roles with permissions the model
+    /// can hold without reallocating, like the arguments of `with_capacity`.
+    pub fn capacity(&self) -> (usize, usize) {
+        (
+            self.user_role_map.capacity(),
+            self.role_permisson_map.capacity(),
+        )
+    }
+
+    /// Shrinks the memory of the role assignments and permissions, including the sets of each
+    /// user and role, as much as possible, e.g. after removing many of them.
+    pub fn shrink_to_fit(&mut self) {
+        self.user_role_map.retain(|_, roles| !roles.is_empty());
+        self.role_permisson_map
+            .retain(|_, permissions| !permissions.is_empty());
+        for roles in self.user_role_map.values_mut() {
+            roles.shrink_to_fit();
+        }
+        for permissions in self.role_permisson_map.values_mut() {
+            permissions.shrink_to_fit();
+        }
+        self.user_role_map.shrink_to_fit();
+        self.role_permisson_map.shrink_to_fit();
+    }
+
+    /// Limits the number of permissions a single role may hold.
+    ///
+    /// Once a role holds `limit` permissions, adding another one fails with
+    /// `InMemoryRbacError::PermissionLimitExceeded`. Roles already holding more permissions than
+    /// the limit keep them.
+    pub fn set_max_permissions_per_role(&mut self, limit: usize) {
+        self.max_permissions_per_role = Some(limit);
+    }
+
+    /// Sets how many keys `assign_role_idempotent` remembers, forgetting the oldest ones first.
+    ///
+    /// Defaults to `DEFAULT_IDEMPOTENCY_KEY_CAPACITY`.
+    pub fn set_idempotency_key_capacity(&mut self, capacity: usize) {
+        self.idempotency_keys.set_capacity(capacity);
+    }
+
+    /// Limits how deeply roles may inherit from each other and permission groups may be nested,
+    /// so that resolving them stays cheap.
+    ///
+    /// A role with a parent has a depth of `1`, a role whose parent has a parent a depth of `2`
+    /// and so on, and likewise for groups. Adding a parent or defining a group that would exceed
+    /// the limit fails with `InMemoryRbacError::ResolutionDepthExceeded`. Hierarchies already
+    /// deeper than the limit are kept. Defaults to `DEFAULT_MAX_RESOLUTION_DEPTH`.
+    pub fn set_max_resolution_depth(&mut self, depth: usize) {
+        self.max_resolution_depth = depth;
+    }
+
+    /// Replaces the clock that decides whether role assignments and emergency overrides have
+    /// expired, e.g. to test expiry without waiting. Defaults to `SystemTime::now`.
+    pub fn set_clock(&mut self, clock: fn() -> SystemTime) {
+        self.clock = clock;
+    }
+
+    /// Starts recording every change to role assignments and permissions in an audit log.
+    ///
+    /// Only the most recent `capacity` entries are kept. Calling this again clears the log.
+    pub fn enable_audit(&mut self, capacity: usize) {
+        self.audit_log = Some(AuditLog::new(capacity));
+    }
+
+    /// Stops recording changes and discards the audit log.
+    pub fn disable_audit(&mut self) {
+        self.audit_log = None;
+    }
+
+    fn record(&mut self, action: RbacChange<U::Id, R::Id, P::Id>) {
+        if let Some(log) = &mut self.audit_log {
+            log.push(action);
+        }
+    }
+
+    /// Checks whether the user has the permission, given either as a `&P` or as a `P::Id`.
+    ///
+    /// This is equivalent to `RbacModel::user_has_permission`, but also accepts bare ids.
+    pub fn user_has_permission<A, M>(
+        &self,
+        user: &U,
+        permission: A,
+    ) -> Result<bool, InMemoryRbacError>
+    where
+        A: AsPermission<P, M>,
+    {
+        Ok(self.has_permission_id(&user.get_rbac_id(), &permission.into_permission_id()))
+    }
+
+    /// Creates an iterator over the `Id`s of the roles of a user that knows its exact length.
+    ///
+    /// Like `RbacIterators::iter_user_role_ids`, this fails with
+    /// `InMemoryRbacError::UserHasNoRoles` if the user has no roles.
+    pub fn iter_user_role_ids_exact(
+        &self,
+        user: &U,
+    ) -> Result<impl ExactSizeIterator<Item = R::Id> + '_, InMemoryRbacError>
+    where
+        R::Id: Clone,
+    {
+        let roles: Vec<R::Id> = self.active_roles(&user.get_rbac_id()).cloned().collect();
+        if roles.is_empty() {
+            return Err(InMemoryRbacError::UserHasNoRoles);
+        }
+        Ok(roles.into_iter())
+    }
+
+    /// Creates an iterator over the `Id`s of all roles of a user, including roles whose
+    /// assignment has expired, e.g. for audits.
+    ///
+    /// `RbacIterators::iter_user_role_ids` only yields the roles that haven't expired. This fails
+    /// with `InMemoryRbacError::UserHasNoRoles` if the user has no roles, expired or not.
+    pub fn iter_user_role_ids_all(
+        &self,
+        user: &U,
+    ) -> Result<impl Iterator<Item = R::Id> + '_, InMemoryRbacError>
+    where
+        R::Id: Clone,
+    {
+        match self.user_role_map.get(&user.get_rbac_id()) {
+            Some(roles) if !roles.is_empty() => Ok(roles.iter().cloned()),
+            _ => Err(InMemoryRbacError::UserHasNoRoles),
+        }
+    }
+
+    /// Checks whether the user has the permission, reusing the result stored in `cache` if there
+    /// is one.
+    ///
+    /// The cache has to be discarded by the caller once the model changes.
+    pub fn user_has_permission_cached(
+        &self,
+        cache: &mut CheckCache<U::Id, P::Id>,
+        user: &U,
+        permission: &P,
+    ) -> Result<bool, InMemoryRbacError> {
+        let key = (user.get_rbac_id(), permission.get_rbac_id());
+        if let Some(&result) = cache.results.get(&key) {
+            return Ok(result);
+        }
+        let result = self.has_permission_id(&key.0, &key.1);
+        cache.results.insert(key, result);
+        Ok(result)
+    }
+
+    /// Checks whether the user has the permission and returns an `AccessDenied` error if not.
+    ///
+    /// This is meant to be used with `?` in request handlers.
+    pub fn ensure_permission(
+        &self,
+        user: &U,
+        permission: &P,
+    ) -> Result<(), AccessDenied<U::Id, P::Id>> {
+        let user = user.get_rbac_id();
+        let permission = permission.get_rbac_id();
+        if self.has_permission_id(&user, &permission) {
+            Ok(())
+        } else {
+            Err(AccessDenied { user, permission })
+        }
+    }
+
+    /// Checks whether the user has at least one of the permissions, stopping at the first one
+    /// the user has.
+    ///
+    /// Returns `false` if `permissions` is empty.
+    pub fn user_has_any_permission(
+        &self,
+        user: &U,
+        permissions: &[P],
+    ) -> Result<bool, InMemoryRbacError> {
+        let user = user.get_rbac_id();
+        Ok(permissions
+            .iter()
+            .any(|permission| self.has_permission_id(&user, &permission.get_rbac_id())))
+    }
+
+    /// Checks whether the user has all of the permissions, stopping at the first one the user
+    /// doesn't have.
+    ///
+    /// Returns `true` if `permissions` is empty.
+    pub fn user_has_all_permissions(
+        &self,
+        user: &U,
+        permissions: &[P],
+    ) -> Result<bool, InMemoryRbacError> {
+        let user = user.get_rbac_id();
+        Ok(permissions
+            .iter()
+            .all(|permission| self.has_permission_id(&user, &permission.get_rbac_id())))
+    }
+
+    /// Returns the id of the first of the permissions the user doesn't have, e.g. to report which
+    /// precondition failed.
+    ///
+    /// Returns `None` if the user has all of them or `permissions` is empty.
+    pub fn first_missing_permission(
+        &self,
+        user: &U,
+        permissions: &[P],
+    ) -> Result<Option<P::Id>, InMemoryRbacError> {
+        let user = user.get_rbac_id();
+        Ok(permissions
+            .iter()
+            .map(Identifiable::get_rbac_id)
+            .find(|permission| !self.has_permission_id(&user, permission)))
+    }
+
+    /// Checks whether any role of the user grants the permission and all of its prerequisites.
+    ///
+    /// This is the hot path behind `user_has_permission` and doesn't allocate, as long as
+    /// `get_rbac_id` doesn't.
+    pub fn any_role_grants(&self, user: &U, permission: &P) -> bool {
+        self.has_permission_id(&user.get_rbac_id(), &permission.get_rbac_id())
+    }
+
+    /// Checks for each of a fixed number of permissions whether the user has it, in the order of
+    /// `permissions`.
+    ///
+    /// This doesn't allocate, e.g. for hot paths that always check the same permissions.
+    pub fn check_fixed<const N: usize>(
+        &self,
+        user: &U,
+        permissions: [&P; N],
+    ) -> Result<[bool; N], InMemoryRbacError> {
+        let user = user.get_rbac_id();
+        Ok(permissions.map(|permission| self.has_permission_id(&user, &permission.get_rbac_id())))
+    }
+
+    /// Makes `permission` only effective for users that also have `prerequisite`.
+    ///
+    /// Prerequisites are checked transitively, so `prerequisite` may have a prerequisite
+    /// itself. A permission has at most one prerequisite, setting another one replaces it. Fails
+    /// with `InMemoryRbacError::PrerequisiteCycle` if `prerequisite` directly or indirectly
+    /// requires `permission`.
+    pub fn set_permission_prerequisite(
+        &mut self,
+        permission: &P,
+        prerequisite: &P,
+    ) -> Result<(), InMemoryRbacError> {
+        self.set_permission_prerequisite_id(permission.get_rbac_id(), prerequisite.get_rbac_id())
+    }
+
+    fn set_permission_prerequisite_id(
+        &mut self,
+        permission: P::Id,
+        prerequisite: P::Id,
+    ) -> Result<(), InMemoryRbacError> {
+        let mut current = &prerequisite;
+        loop {
+            if *current == permission {
+                return Err(InMemoryRbacError::PrerequisiteCycle);
+            }
+            match self.prerequisite_map.get(current) {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+        self.prerequisite_map.insert(permission, prerequisite);
+        Ok(())
+    }
+
+    /// Makes `group` stand for all of `members`, so that a role with `group` grants each of
+    /// them, replacing any previous members.
+    ///
+    /// Members may be groups themselves and are expanded transitively. Defining a group without
+    /// members removes it. Fails with `InMemoryRbacError::PermissionGroupCycle` if `group` would
+    /// directly or indirectly contain itself and with `InMemoryRbacError::ResolutionDepthExceeded`
+    /// if the groups would be nested deeper than allowed by `set_max_resolution_depth`.
+    pub fn define_permission_group(
+        &mut self,
+        group: &P,
+        members: &[P],
+    ) -> Result<(), InMemoryRbacError> {
+        self.define_permission_group_id(
+            group.get_rbac_id(),
+            members.iter().map(Identifiable::get_rbac_id).collect(),
+        )
+    }
+
+    fn define_permission_group_id(
+        &mut self,
+        group: P::Id,
+        members: HashSet<P::Id>,
+    ) -> Result<(), InMemoryRbacError> {
+        self.materialized_map = None;
+        if members
+            .iter()
+            .any(|member| *member == group || self.group_contains(member, &group))
+        {
+            return Err(InMemoryRbacError::PermissionGroupCycle);
+        }
+        if !members.is_empty() {
+            let containing = Self::chain_depth(
+                &Self::invert_relation(&self.permission_group_map),
+                &&group,
+                &mut HashMap::new(),
+            );
+            let mut memo = HashMap::new();
+            let nested = members
+                .iter()
+                .map(|member| Self::chain_depth(&self.permission_group_map, member, &mut memo))
+                .max()
+                .unwrap_or(0);
+            if containing + 1 + nested > self.max_resolution_depth {
+                return Err(InMemoryRbacError::ResolutionDepthExceeded);
+            }
+        }
+        if members.is_empty() {
+            self.permission_group_map.remove(&group);
+        } else {
+            self.permission_group_map.insert(group, members);
+        }
+        Ok(())
+    }
+
+    /// Checks whether `group` directly or indirectly contains `permission`.
+    fn group_contains(&self, group: &P::Id, permission: &P::Id) -> bool {
+        match self.permission_group_map.get(group) {
+            Some(members) => {
+                members.contains(permission)
+                    || members
+                        .iter()
+                        .any(|member| self.group_contains(member, permission))
+            }
+            None => false,
+        }
+    }
+
+    /// Adds the members of `group` and of all groups among them to `permissions`.
+    fn collect_group_members<'a>(&'a self, group: &P::Id, permissions: &mut HashSet<&'a P::Id>) {
+        for member in self.permission_group_map.get(group).into_iter().flatten() {
+            if permissions.insert(member) {
+                self.collect_group_members(member, permissions);
+            }
+        }
+    }
+
+    /// Removes the prerequisite of `permission`, returning whether it had one.
+    pub fn remove_permission_prerequisite(&mut self, permission: &P) -> bool {
+        self.prerequisite_map
+            .remove(&permission.get_rbac_id())
+            .is_some()
+    }
+
+    /// Makes `role` inherit all permissions of `parent`, including the ones `parent` inherits
+    /// itself, returning whether `parent` wasn't a parent of `role` before.
+    ///
+    /// Fails with `InMemoryRbacError::RoleHierarchyCycle` if `role` would directly or indirectly
+    /// be its own parent and with `InMemoryRbacError::ResolutionDepthExceeded` if the hierarchy
+    /// would be deeper than allowed by `set_max_resolution_depth`.
+    pub fn add_role_parent(&mut self, role: &R, parent: &R) -> Result<bool, InMemoryRbacError> {
+        self.add_role_parent_id(role.get_rbac_id(), parent.get_rbac_id())
+    }
+
+    fn add_role_parent_id(
+        &mut self,
+        role: R::Id,
+        parent: R::Id,
+    ) -> Result<bool, InMemoryRbacError> {
+        self.materialized_map = None;
+        if self.inherits_from(&parent, &role) {
+            return Err(InMemoryRbacError::RoleHierarchyCycle);
+        }
+        if let Some(parents) = self.role_parent_map.get(&role) {
+            if parents.contains(&parent) {
+                return Ok(false);
+            }
+        }
+        let inheriting = Self::chain_depth(
+            &Self::invert_relation(&self.role_parent_map),
+            &&role,
+            &mut HashMap::new(),
+        );
+        let inherited = Self::chain_depth(&self.role_parent_map, &parent, &mut HashMap::new());
+        if inheriting + 1 + inherited > self.max_resolution_depth {
+            return Err(InMemoryRbacError::ResolutionDepthExceeded);
+        }
+        Ok(self.role_parent_map.entry(role).or_default().insert(parent))
+    }
+
+    /// Returns the length of the longest path starting at `start` in an acyclic `relation`.
+    fn chain_depth<'a, T: Eq + Hash>(
+        relation: &'a HashMap<T, HashSet<T>>,
+        start: &'a T,
+        memo: &mut HashMap<&'a T, usize>,
+    ) -> usize {
+        if let Some(&depth) = memo.get(start) {
+            return depth;
+        }
+        let depth = match relation.get(start) {
+            Some(next) => {
+                1 + next
+                    .iter()
+                    .map(|item| Self::chain_depth(relation, item, memo))
+                    .max()
+                    .unwrap_or(0)
+            }
+            None => 0,
+        };
+        memo.insert(start, depth);
+        depth
+    }
+
+    /// Reverses every pair of a relation, e.g. to map each role to the roles inheriting from it.
+    fn invert_relation<T: Eq + Hash>(
+        relation: &HashMap<T, HashSet<T>>,
+    ) -> HashMap<&T, HashSet<&T>> {
+        let mut inverted: HashMap<&T, HashSet<&T>> = HashMap::new();
+        for (from, to) in relation {
+            for item in to {
+                inverted.entry(item).or_default().insert(from);
+            }
+        }
+        inverted
+    }
+
+    /// Checks whether `role` is `ancestor` or directly or indirectly inherits from it.
+    fn inherits_from(&self, role: &R::Id, ancestor: &R::Id) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![role];
+        while let Some(current) = stack.pop() {
+            if current == ancestor {
+                return true;
+            }
+            if visited.insert(current) {
+                stack.extend(self.role_parent_map.get(current).into_iter().flatten());
+            }
+        }
+        false
+    }
+
+    /// Stops `role` from inheriting the permissions of `parent`, returning whether `parent` was a
+    /// parent of `role`.
+    pub fn remove_role_parent(&mut self, role: &R, parent: &R) -> bool {
+        self.materialized_map = None;
+        let role = role.get_rbac_id();
+        match self.role_parent_map.get_mut(&role) {
+            Some(parents) => {
+                let was_present = parents.remove(&parent.get_rbac_id());
+                if parents.is_empty() {
+                    self.role_parent_map.remove(&role);
+                }
+                was_present
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the ids of the roles `role` inherits from within `hops` steps up the hierarchy,
+    /// e.g. to preview the effective permissions of a role one level at a time.
+    ///
+    /// With `hops` set to `1` only the direct parents are returned. The role itself isn't part
+    /// of the result and the order of the roles is unspecified.
+    pub fn roles_within(&self, role: &R, hops: usize) -> Vec<R::Id>
+    where
+        R::Id: Clone,
+    {
+        let role = role.get_rbac_id();
+        let mut visited: HashSet<&R::Id> = HashSet::new();
+        let mut frontier = vec![&role];
+        for _ in 0..hops {
+            frontier = frontier
+                .into_iter()
+                .flat_map(|current| self.role_parent_map.get(current).into_iter().flatten())
+                .filter(|parent| **parent != role && visited.insert(*parent))
+                .collect();
+            if frontier.is_empty() {
+                break;
+            }
+        }
+        visited.into_iter().cloned().collect()
+    }
+
+    /// Returns the ids of the permissions attached directly to the role, leaving out the ones it
+    /// inherits from its parents, e.g. to tell owned and inherited grants apart.
+    ///
+    /// Like `RbacIterators::iter_role_permission_ids`, this fails with
+    /// `InMemoryRbacError::RoleHasNoPermissions` if the role has no permissions of its own.
+    pub fn role_own_permissions(&self, role: &R) -> Result<Vec<P::Id>, InMemoryRbacError>
+    where
+        P::Id: Clone,
+    {
+        match self.role_permisson_map.get(&role.get_rbac_id()) {
+            Some(permissions) if !permissions.is_empty() => {
+                Ok(permissions.iter().cloned().collect())
+            }
+            _ => Err(InMemoryRbacError::RoleHasNoPermissions),
+        }
+    }
+
+    /// Resolves the full set of permissions of every role once, including inherited permissions
+    /// and members of permission groups, so that later checks are flat lookups instead of walks
+    /// through the hierarchy.
+    ///
+    /// The resolved sets are discarded by every change to permissions, role parents or
+    /// permission groups, after which this has to be called again. Fails with
+    /// `InMemoryRbacError::RoleHierarchyCycle` if the hierarchy contains a cycle.
+    pub fn materialize_inheritance(&mut self) -> Result<(), InMemoryRbacError>
+    where
+        R::Id: Clone,
+        P::Id: Clone,
+    {
+        self.materialized_map = None;
+        for (role, parents) in &self.role_parent_map {
+            if parents
+                .iter()
+                .any(|parent| self.inherits_from(parent, role))
+            {
+                return Err(InMemoryRbacError::RoleHierarchyCycle);
+            }
+        }
+        let materialized = self
+            .role_permisson_map
+            .keys()
+            .chain(self.role_parent_map.keys())
+            .filter_map(|role| {
+                let permissions = self.role_permission_set(role);
+                if permissions.is_empty() {
+                    return None;
+                }
+                Some((role.clone(), permissions.into_iter().cloned().collect()))
+            })
+            .collect();
+        self.materialized_map = Some(materialized);
+        Ok(())
+    }
+
+    /// Makes `permission` only effective for users that hold at least `min_roles` roles, no matter
+    /// which roles grant it.
+    ///
+    /// Setting `min_roles` to `0` or `1` removes the requirement.
+    pub fn require_min_roles_for_permission(&mut self, permission: &P, min_roles: usize) {
+        if min_roles > 1 {
+            self.min_roles_map
+                .insert(permission.get_rbac_id(), min_roles);
+        } else {
+            self.min_roles_map.remove(&permission.get_rbac_id());
+        }
+    }
+
+    /// Attaches descriptive metadata to a permission, returning the previous metadata if any.
+    ///
+    /// Metadata is purely informational and doesn't affect any checks.
+    pub fn set_permission_meta(
+        &mut self,
+        permission: &P,
+        meta: PermissionMeta,
+    ) -> Option<PermissionMeta> {
+        self.permission_meta.insert(permission.get_rbac_id(), meta)
+    }
+
+    /// Returns the metadata attached to a permission with `set_permission_meta`.
+    pub fn get_permission_meta(&self, permission: &P) -> Option<&PermissionMeta> {
+        self.permission_meta.get(&permission.get_rbac_id())
+    }
+
+    fn has_permission_id(&self, user: &U::Id, permission: &P::Id) -> bool {
+        self.has_permission_id_without(user, permission, None)
+    }
+
+    /// Like `has_permission_id`, but pretends the user doesn't have the role `without`.
+    fn has_permission_id_without(
+        &self,
+        user: &U::Id,
+        permission: &P::Id,
+        without: Option<&R::Id>,
+    ) -> bool {
+        if self.has_emergency_override(user) {
+            return true;
+        }
+        let mut current = permission;
+        loop {
+            if self.is_denied(user, current)
+                || !self.role_grants_id(user, current, without)
+                || !self.has_min_roles(user, current, without)
+            {
+                return false;
+            }
+            match self.prerequisite_map.get(current) {
+                Some(prerequisite) => current = prerequisite,
+                None => return true,
+            }
+        }
+    }
+
+    fn is_denied(&self, user: &U::Id, permission: &P::Id) -> bool {
+        match self.user_denied_map.get(user) {
+            Some(denied) => denied.contains(permission),
+            None => false,
+        }
+    }
+
+    fn has_emergency_override(&self, user: &U::Id) -> bool {
+        match self.emergency_overrides.get(user) {
+            Some(until) => (self.clock)() < *until,
+            None => false,
+        }
+    }
+
+    /// Checks whether the assignment of the role to the user hasn't expired, assuming the user
+    /// has the role.
+    fn role_active(&self, user: &U::Id, role: &R::Id) -> bool {
+        match self
+            .role_expiry_map
+            .get(user)
+            .and_then(|roles| roles.get(role))
+        {
+            Some(until) => (self.clock)() < *until,
+            None => true,
+        }
+    }
+
+    /// Creates an iterator over the roles of the user whose assignment hasn't expired.
+    fn active_roles<'a>(&'a self, user: &'a U::Id) -> impl Iterator<Item = &'a R::Id> + 'a {
+        self.user_role_map
+            .get(user)
+            .into_iter()
+            .flatten()
+            .filter(move |role| self.role_active(user, role))
+    }
+
+    fn has_min_roles(&self, user: &U::Id, permission: &P::Id, without: Option<&R::Id>) -> bool {
+        let min_roles = match self.min_roles_map.get(permission) {
+            Some(&val) => val,
+            None => return true,
+        };
+        let roles = self
+            .active_roles(user)
+            .filter(|&role| Some(role) != without)
+            .count();
+        roles >= min_roles
+    }
+
+    fn role_grants_id(&self, user: &U::Id, permission: &P::Id, without: Option<&R::Id>) -> bool {
+        self.active_roles(user)
+            .any(|r| Some(r) != without && self.role_has_permission_id(r, permission))
+    }
+
+    /// Checks whether the role or one of its ancestors has the permission.
+    fn role_has_permission_id(&self, role: &R::Id, permission: &P::Id) -> bool {
+        if let Some(materialized) = &self.materialized_map {
+            return materialized
+                .get(role)
+                .is_some_and(|permissions| permissions.contains(permission));
+        }
+        let granted = match self.role_permisson_map.get(role) {
+            Some(val) => {
+                val.contains(permission)
+                    || (!self.permission_group_map.is_empty()
+                        && val.iter().any(|p| self.group_contains(p, permission)))
+            }
+            None => false,
+        };
+        granted
+            || match self.role_parent_map.get(role) {
+                Some(parents) => parents
+                    .iter()
+                    .any(|parent| self.role_has_permission_id(parent, permission)),
+                None => false,
+            }
+    }
+
+    /// Adds the permissions of the role and all of its ancestors to `permissions`.
+    fn collect_role_permissions<'a>(&'a self, role: &R::Id, permissions: &mut HashSet<&'a P::Id>) {
+        if let Some(materialized) = &self.materialized_map {
+            permissions.extend(materialized.get(role).into_iter().flatten());
+            return;
+        }
+        if let Some(val) = self.role_permisson_map.get(role) {
+            permissions.extend(val);
+            if !self.permission_group_map.is_empty() {
+                for p in val {
+                    self.collect_group_members(p, permissions);
+                }
+            }
+        }
+        for parent in self.role_parent_map.get(role).into_iter().flatten() {
+            self.collect_role_permissions(parent, permissions);
+        }
+    }
+
+    /// Collects the permissions of the role including inherited ones.
+    fn role_permission_set(&self, role: &R::Id) -> HashSet<&P::Id> {
+        let mut permissions = HashSet::new();
+        self.collect_role_permissions(role, &mut permissions);
+        permissions
+    }
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable, S: BuildHasher + Clone>
+    InMemoryRbac<U, R, P, S>
+where
+    U::Id: Eq + Hash + Clone,
+    R::Id: Eq + Hash + Clone,
+    P::Id: Eq + Hash + Clone,
+{
+    /// Replaces all role assignments so that they exactly match `desired`.
+    ///
+    /// Missing assignments are added and assignments that are not part of `desired` are removed,
+    /// including those of users that don't appear in `desired` at all. Expired assignments count
+    /// as missing and are added again without an expiry, while assignments that are kept keep
+    /// their expiry time.
+    ///
+    /// The added assignments are checked against the constraints `assign_role` enforces, based
+    /// on the desired assignments instead of the current ones. If one of them is violated, this
+    /// fails like `assign_role`, with `InMemoryRbacError::MissingPrerequisiteRole` or
+    /// `InMemoryRbacError::ConstraintViolated`, without changing anything.
+    pub fn sync_from_map(
+        &mut self,
+        desired: &HashMap<U::Id, HashSet<R::Id>>,
+    ) -> Result<SyncReport, InMemoryRbacError> {
+        let mut removed = Vec::new();
+        for (user, roles) in &self.user_role_map {
+            let desired_roles = desired.get(user);
+            for role in roles {
+                if !desired_roles.is_some_and(|d| d.contains(role)) && self.role_active(user, role)
+                {
+                    removed.push((user.clone(), role.clone()));
+                }
+            }
+        }
+        let mut added = Vec::new();
+        for (user, desired_roles) in desired {
+            let roles = self.user_role_map.get(user);
+            for role in desired_roles {
+                if !roles.is_some_and(|r| r.contains(role)) || !self.role_active(user, role) {
+                    added.push((user.clone(), role.clone()));
+                }
+            }
+        }
+        if self.has_assignment_constraints() {
+            for (user, role) in &added {
+                self.check_desired_assignment(desired, user, role)?;
+            }
+        }
+
+        self.user_role_map
+            .retain(|user, _| desired.contains_key(user));
+        for (user, desired_roles) in desired {
+            if desired_roles.is_empty() {
+                self.user_role_map.remove(user);
+            } else {
+                self.user_role_map
+                    .insert(user.clone(), desired_roles.clone());
+            }
+        }
+
+        let user_role_map = &self.user_role_map;
+        self.assigned_by_map
+            .retain(|user, grants| match user_role_map.get(user) {
+                Some(roles) => {
+                    grants.retain(|role, _| roles.contains(role));
+                    !grants.is_empty()
+                }
+                None => false,
+            });
+        let now = (self.clock)();
+        self.role_expiry_map.retain(|user, expiries| {
+            let roles = user_role_map.get(user);
+            expiries.retain(|role, until| now < *until && roles.is_some_and(|r| r.contains(role)));
+            !expiries.is_empty()
+        });
+
+        let report = SyncReport {
+            added: added.len(),
+            removed: removed.len(),
+        };
+        for (user, role) in removed {
+            self.record(RbacChange::UnassignRole { user, role });
+        }
+        for (user, role) in added {
+            self.record(RbacChange::AssignRole { user, role });
+        }
+        Ok(report)
+    }
+
+    /// Checks whether assigning the role to the user violates a constraint, if every user had
+    /// exactly the `desired` roles.
+    fn check_desired_assignment(
+        &self,
+        desired: &HashMap<U::Id, HashSet<R::Id>>,
+        user: &U::Id,
+        role: &R::Id,
+    ) -> Result<(), ConstraintViolation> {
+        let roles = &desired[user];
+        if self.strict_roles
+            && !self.role_permisson_map.contains_key(role)
+            && !self.role_parent_map.contains_key(role)
+        {
+            return Err(ConstraintViolation::UnknownRole);
+        }
+        if let Some(prerequisites) = self.role_prerequisite_map.get(role) {
+            if !prerequisites.is_subset(roles) {
+                return Err(ConstraintViolation::MissingPrerequisiteRole);
+            }
+        }
+        if let Some(exclusive) = self.exclusive_roles_map.get(role) {
+            if !exclusive.is_disjoint(roles) {
+                return Err(ConstraintViolation::MutuallyExclusiveRole);
+            }
+        }
+        if let Some(&limit) = self.max_users_per_role_map.get(role) {
+            let holders = desired
+                .values()
+                .filter(|roles| roles.contains(role))
+                .count();
+            if holders > limit {
+                return Err(ConstraintViolation::MaxUsersPerRoleExceeded);
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies a single change, e.g. one received from a change feed.
+    ///
+    /// Returns whether the change had any effect, like the corresponding method of `RbacModel`.
+    pub fn apply_change(
+        &mut self,
+        change: RbacChange<U::Id, R::Id, P::Id>,
+    ) -> Result<bool, InMemoryRbacError> {
+        match change {
+            RbacChange::AssignRole { user, role } => self.assign_role_id(user, role),
+            RbacChange::UnassignRole { user, role } => self.unassign_role_id(&user, &role),
+            RbacChange::AddPermission { role, permission } => {
+                self.add_permission_id(role, permission)
+            }
+            RbacChange::RemovePermission { role, permission } => {
+                self.remove_permission_id(&role, &permission)
+            }
+            RbacChange::EmergencyOverride { user, until } => {
+                Ok(self.emergency_override_id(user, until))
+            }
+            RbacChange::AssignRoleUntil { user, role, until } => {
+                self.assign_role_until_id(user, role, until)
+            }
+            RbacChange::DenyPermission { user, permission } => {
+                Ok(self.deny_permission_id(user, permission))
+            }
+            RbacChange::AllowPermission { user, permission } => {
+                Ok(self.allow_permission_id(&user, &permission))
+            }
+        }
+    }
+
+    fn assign_role_id(&mut self, user: U::Id, role: R::Id) -> Result<bool, InMemoryRbacError> {
+        self.check_assign_id(&user, &role)?;
+        let expired = !self.role_active(&user, &role);
+        self.remove_role_expiry(&user, &role);
+        let entry = self.user_role_map.entry(user.clone()).or_default();
+
+        let assigned = entry.insert(role.clone()) || expired;
+        if assigned {
+            self.record(RbacChange::AssignRole { user, role });
+        }
+        Ok(assigned)
+    }
+
+    fn assign_role_until_id(
+        &mut self,
+        user: U::Id,
+        role: R::Id,
+        until: SystemTime,
+    ) -> Result<bool, InMemoryRbacError> {
+        self.check_assign_id(&user, &role)?;
+        let had_role = match self.user_role_map.get(&user) {
+            Some(roles) => roles.contains(&role) && self.role_active(&user, &role),
+            None => false,
+        };
+        self.user_role_map
+            .entry(user.clone())
+            .or_default()
+            .insert(role.clone());
+        self.role_expiry_map
+            .entry(user.clone())
+            .or_default()
+            .insert(role.clone(), until);
+        self.record(RbacChange::AssignRoleUntil { user, role, until });
+        Ok(!had_role)
+    }
+
+    fn remove_role_expiry(&mut self, user: &U::Id, role: &R::Id) {
+        if let Entry::Occupied(mut expiries) = self.role_expiry_map.entry(user.clone()) {
+            expiries.get_mut().remove(role);
+            if expiries.get().is_empty() {
+                expiries.remove_entry();
+            }
+        }
+    }
+
+    fn unassign_role_id(&mut self, user: &U::Id, role: &R::Id) -> Result<bool, InMemoryRbacError> {
+        let was_present = match self.user_role_map.get_mut(user) {
+            Some(roles) => {
+                let was_present = roles.remove(role);
+                if roles.is_empty() {
+                    self.user_role_map.remove(user);
+                }
+                was_present
+            }
+            None => return Ok(false),
+        };
+        if let Entry::Occupied(mut grants) = self.assigned_by_map.entry(user.clone()) {
+            grants.get_mut().remove(role);
+            if grants.get().is_empty() {
+                grants.remove_entry();
+            }
+        }
+        self.remove_role_expiry(user, role);
+        if was_present {
+            self.record(RbacChange::UnassignRole {
+                user: user.clone(),
+                role: role.clone(),
+            });
+        }
+        Ok(was_present)
+    }
+
+    fn add_permission_id(
+        &mut self,
+        role: R::Id,
+        permission: P::Id,
+    ) -> Result<bool, InMemoryRbacError> {
+        self.materialized_map = None;
+        let entry = self.role_permisson_map.entry(role.clone()).or_default();
+        if let Some(limit) = self.max_permissions_per_role {
+            if entry.len() >= limit && !entry.contains(&permission) {
+                if entry.is_empty() {
+                    self.role_permisson_map.remove(&role);
+                }
+                return Err(InMemoryRbacError::PermissionLimitExceeded);
+            }
+        }
+        let added = entry.insert(permission.clone());
+        if added {
+            self.record(RbacChange::AddPermission { role, permission });
+        }
+        Ok(added)
+    }
+
+    fn remove_permission_id(
+        &mut self,
+        role: &R::Id,
+        permission: &P::Id,
+    ) -> Result<bool, InMemoryRbacError> {
+        self.materialized_map = None;
+        let was_present = match self.role_permisson_map.get_mut(role) {
+            Some(permissions) => {
+                let was_present = permissions.remove(permission);
+                if permissions.is_empty() {
+                    self.role_permisson_map.remove(role);
+                }
+                was_present
+            }
+            None => return Ok(false),
+        };
+        if was_present {
+            self.record(RbacChange::RemovePermission {
+                role: role.clone(),
+                permission: permission.clone(),
+            });
+        }
+        Ok(was_present)
+    }
+
+    /// Returns where a permission of a user comes from, or `None` if the user doesn't have it.
+    ///
+    /// A role the permission is attached to is preferred over a parent role, which is preferred
+    /// over a permission group, and the shortest chain of parents is reported. If multiple roles
+    /// grant the permission in the same way, any one of them may be returned.
+    pub fn permission_source(
+        &self,
+        user: &U,
+        permission: &P,
+    ) -> Option<PermissionSource<R::Id, P::Id>> {
+        let user = user.get_rbac_id();
+        let permission = permission.get_rbac_id();
+        if !self.has_permission_id(&user, &permission) {
+            return None;
+        }
+        let source = self
+            .active_roles(&user)
+            .filter_map(|role| self.role_source(role, &permission))
+            .min_by_key(|source| match source {
+                PermissionSource::Role(_) => 0,
+                PermissionSource::Inherited { .. } => 1,
+                _ => 2,
+            });
+        match source {
+            Some(source) => Some(source),
+            None => self
+                .emergency_overrides
+                .get(&user)
+                .map(|&until| PermissionSource::EmergencyOverride { until }),
+        }
+    }
+
+    /// Finds out how the role grants the permission, walking up the hierarchy breadth first so
+    /// the shortest chain of parents is found.
+    fn role_source(
+        &self,
+        role: &R::Id,
+        permission: &P::Id,
+    ) -> Option<PermissionSource<R::Id, P::Id>> {
+        let mut group_source = None;
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::from([(role, Vec::new())]);
+        while let Some((current, chain)) = queue.pop_front() {
+            if !visited.insert(current) {
+                continue;
+            }
+            if let Some(permissions) = self.role_permisson_map.get(current) {
+                if permissions.contains(permission) {
+                    if chain.is_empty() {
+                        return Some(PermissionSource::Role(role.clone()));
+                    }
+                    return Some(PermissionSource::Inherited {
+                        role: role.clone(),
+                        chain,
+                    });
+                }
+                if group_source.is_none() {
+                    group_source = permissions
+                        .iter()
+                        .find(|group| self.group_contains(group, permission))
+                        .map(|group| PermissionSource::Group {
+                            role: role.clone(),
+                            chain: chain.clone(),
+                            group: group.clone(),
+                        });
+                }
+            }
+            for parent in self.role_parent_map.get(current).into_iter().flatten() {
+                let mut chain = chain.clone();
+                chain.push(parent.clone());
+                queue.push_back((parent, chain));
+            }
+        }
+        group_source
+    }
+
+    /// Returns the id of a role of the user that grants the permission, or `None` if the user
+    /// doesn't have the permission through a role.
+    ///
+    /// If multiple roles grant the permission, any one of them may be returned.
+    pub fn which_role_grants(
+        &self,
+        user: &U,
+        permission: &P,
+    ) -> Result<Option<R::Id>, InMemoryRbacError> {
+        Ok(self.iter_roles_granting(user, permission).next())
+    }
+
+    /// Creates an iterator over the ids of all roles of the user that grant the permission,
+    /// directly or through a parent role.
+    ///
+    /// Nothing is yielded if the user doesn't have the permission, e.g. because a prerequisite
+    /// is missing. The order of the roles is unspecified.
+    pub fn iter_roles_granting(&self, user: &U, permission: &P) -> std::vec::IntoIter<R::Id> {
+        let user = user.get_rbac_id();
+        let permission = permission.get_rbac_id();
+        if !self.has_permission_id(&user, &permission) {
+            return Vec::new().into_iter();
+        }
+        let roles: Vec<R::Id> = self
+            .active_roles(&user)
+            .filter(|r| self.role_has_permission_id(r, &permission))
+            .cloned()
+            .collect();
+        roles.into_iter()
+    }
+
+    /// Grants a user all permissions until `until`, e.g. to let them act during an incident.
+    ///
+    /// The override replaces any previous override of the user and expires on its own. It is
+    /// always recorded in the audit log, if enabled.
+    pub fn emergency_override(&mut self, user: &U, until: SystemTime) {
+        self.emergency_override_id(user.get_rbac_id(), until);
+    }
+
+    fn emergency_override_id(&mut self, user: U::Id, until: SystemTime) -> bool {
+        let changed = self.emergency_overrides.insert(user.clone(), until) != Some(until);
+        self.record(RbacChange::EmergencyOverride { user, until });
+        changed
+    }
+
+    /// Denies a permission to a user, even if one of the user's roles grants it.
+    ///
+    /// A denied permission also can't serve as a prerequisite of another permission. Emergency
+    /// overrides still grant every permission. Returns whether the permission wasn't denied
+    /// before.
+    pub fn deny_permission(&mut self, user: &U, permission: &P) -> bool {
+        self.deny_permission_id(user.get_rbac_id(), permission.get_rbac_id())
+    }
+
+    /// Lifts a denial added with `deny_permission`, returning whether the permission was denied.
+    pub fn allow_permission(&mut self, user: &U, permission: &P) -> bool {
+        self.allow_permission_id(&user.get_rbac_id(), &permission.get_rbac_id())
+    }
+
+    fn deny_permission_id(&mut self, user: U::Id, permission: P::Id) -> bool {
+        let denied = self
+            .user_denied_map
+            .entry(user.clone())
+            .or_default()
+            .insert(permission.clone());
+        if denied {
+            self.record(RbacChange::DenyPermission { user, permission });
+        }
+        denied
+    }
+
+    fn allow_permission_id(&mut self, user: &U::Id, permission: &P::Id) -> bool {
+        let allowed = match self.user_denied_map.get_mut(user) {
+            Some(denied) => {
+                let allowed = denied.remove(permission);
+                if denied.is_empty() {
+                    self.user_denied_map.remove(user);
+                }
+                allowed
+            }
+            None => false,
+        };
+        if allowed {
+            self.record(RbacChange::AllowPermission {
+                user: user.clone(),
+                permission: permission.clone(),
+            });
+        }
+        allowed
+    }
+
+    /// Assigns a role to a user until `until`, e.g. for an on-call elevation.
+    ///
+    /// Once the assignment has expired, the user is treated as not having the role. Returns
+    /// whether the user didn't have the role before, and fails like `assign_role` if the role
+    /// violates a constraint. Assigning the role again, with or without an expiry, replaces the
+    /// expiry.
+    pub fn assign_role_until(
+        &mut self,
+        user: &U,
+        role: &R,
+        until: SystemTime,
+    ) -> Result<bool, InMemoryRbacError> {
+        self.assign_role_until_id(user.get_rbac_id(), role.get_rbac_id(), until)
+    }
+
+    /// Unassigns all roles whose assignment has expired, returning the number of removed
+    /// assignments.
+    ///
+    /// Expired assignments are ignored by permission checks and role iterators, but are still
+    /// counted by methods that inspect the whole model, like `total_assignments` or
+    /// `fingerprint`, until they are purged.
+    pub fn purge_expired_roles(&mut self) -> usize {
+        let now = (self.clock)();
+        let expired: Vec<(U::Id, R::Id)> = self
+            .role_expiry_map
+            .iter()
+            .flat_map(|(user, roles)| {
+                roles
+                    .iter()
+                    .filter(|&(_, until)| *until <= now)
+                    .map(move |(role, _)| (user.clone(), role.clone()))
+            })
+            .collect();
+        for (user, role) in &expired {
+            self.remove_role_expiry(user, role);
+            let _ = self.unassign_role_id(user, role);
+        }
+        expired.len()
+    }
+
+    /// Renames a role everywhere it appears, returning whether any user had the role or the role
+    /// had any permissions.
+    ///
+    /// If `new` is already in use, both roles are merged: users of either role have the merged
+    /// role, which has the permissions, parents, prerequisites and exclusions of both and the
+    /// lower of their user limits. Of two expiring assignments of the roles to a user, the later
+    /// expiry is kept. Fails with `InMemoryRbacError::PermissionLimitExceeded` if the merged
+    /// role would hold too many permissions and with `InMemoryRbacError::RoleHierarchyCycle` if
+    /// one role inherits from the other, without changing anything.
+    pub fn rename_role(&mut self, old: &R, new: &R) -> Result<bool, InMemoryRbacError> {
+        self.materialized_map = None;
+        let old = old.get_rbac_id();
+        let new = new.get_rbac_id();
+        if old == new {
+            return Ok(self
+                .user_role_map
+                .values()
+                .any(|roles| roles.contains(&old))
+                || self.role_permisson_map.contains_key(&old));
+        }
+        if self.inherits_from(&old, &new) || self.inherits_from(&new, &old) {
+            return Err(InMemoryRbacError::RoleHierarchyCycle);
+        }
+        if let (Some(limit), Some(permissions)) = (
+            self.max_permissions_per_role,
+            self.role_permisson_map.get(&old),
+        ) {
+            let merged = match self.role_permisson_map.get(&new) {
+                Some(other) => permissions.union(other).count(),
+                None => permissions.len(),
+            };
+            if merged > limit {
+                return Err(InMemoryRbacError::PermissionLimitExceeded);
+            }
+        }
+
+        let mut changes = Vec::new();
+        for (user, roles) in self.user_role_map.iter_mut() {
+            if !roles.remove(&old) {
+                continue;
+            }
+            let had_new = !roles.insert(new.clone());
+            changes.push(RbacChange::UnassignRole {
+                user: user.clone(),
+                role: old.clone(),
+            });
+            if !had_new {
+                changes.push(RbacChange::AssignRole {
+                    user: user.clone(),
+                    role: new.clone(),
+                });
+            }
+            if let Some(grants) = self.assigned_by_map.get_mut(user) {
+                if let Some(by) = grants.remove(&old) {
+                    grants.entry(new.clone()).or_insert(by);
+                }
+            }
+            if let Some(expiries) = self.role_expiry_map.get_mut(user) {
+                let old_expiry = expiries.remove(&old);
+                let new_expiry = expiries.remove(&new);
+                let expiry = match (had_new, old_expiry, new_expiry) {
+                    (false, expiry, _) => expiry,
+                    (true, Some(a), Some(b)) => Some(a.max(b)),
+                    (true, _, _) => None,
+                };
+                if let Some(expiry) = expiry {
+                    expiries.insert(new.clone(), expiry);
+                }
+                if expiries.is_empty() {
+                    self.role_expiry_map.remove(user);
+                }
+            }
+        }
+        if let Some(permissions) = self.role_permisson_map.remove(&old) {
+            let merged = self.role_permisson_map.entry(new.clone()).or_default();
+            for permission in permissions {
+                if merged.insert(permission.clone()) {
+                    changes.push(RbacChange::AddPermission {
+                        role: new.clone(),
+                        permission: permission.clone(),
+                    });
+                }
+                changes.push(RbacChange::RemovePermission {
+                    role: old.clone(),
+                    permission,
+                });
+            }
+        }
+        Self::rename_in_relation(&mut self.exclusive_roles_map, &old, &new);
+        Self::rename_in_relation(&mut self.role_prerequisite_map, &old, &new);
+        Self::rename_in_relation(&mut self.role_parent_map, &old, &new);
+        if let Some(limit) = self.max_users_per_role_map.remove(&old) {
+            let merged = self.max_users_per_role_map.entry(new).or_insert(limit);
+            *merged = limit.min(*merged);
+        }
+
+        let renamed = !changes.is_empty();
+        for action in changes {
+            self.record(action);
+        }
+        Ok(renamed)
+    }
+
+    /// Renames `old` to `new` in the keys and values of a relation between roles, merging their
+    /// entries.
+    fn rename_in_relation(map: &mut HashMap<R::Id, HashSet<R::Id>>, old: &R::Id, new: &R::Id) {
+        if let Some(related) = map.remove(old) {
+            map.entry(new.clone()).or_default().extend(related);
+        }
+        map.retain(|role, related| {
+            if related.remove(old) {
+                related.insert(new.clone());
+            }
+            related.remove(role);
+            !related.is_empty()
+        });
+    }
+
+    /// Removes all roles from a user, returning whether the user had any.
+    ///
+    /// Unlike `clear_users`, emergency overrides of the user are kept.
+    pub fn clear_user_roles(&mut self, user: &U) -> Result<bool, InMemoryRbacError> {
+        Ok(self.clear_user_roles_id(user.get_rbac_id()))
+    }
+
+    fn clear_user_roles_id(&mut self, user: U::Id) -> bool {
+        self.assigned_by_map.remove(&user);
+        self.role_expiry_map.remove(&user);
+        let roles = match self.user_role_map.remove(&user) {
+            Some(roles) => roles,
+            None => return false,
+        };
+        for role in roles {
+            self.record(RbacChange::UnassignRole {
+                user: user.clone(),
+                role,
+            });
+        }
+        true
+    }
+
+    /// Moves all roles of user `from` to user `to`, e.g. when merging two accounts, returning the
+    /// number of roles `to` didn't have before.
+    ///
+    /// Expiring assignments keep their expiry, unless `to` already has the role. Fails like
+    /// `assign_role` if a role violates a constraint; the roles before it are already assigned to
+    /// `to`, but `from` keeps all of its roles.
+    pub fn transfer_roles(&mut self, from: &U, to: &U) -> Result<usize, InMemoryRbacError> {
+        let from = from.get_rbac_id();
+        let to = to.get_rbac_id();
+        if from == to {
+            return Ok(0);
+        }
+        let roles: Vec<(R::Id, Option<SystemTime>)> = self
+            .active_roles(&from)
+            .map(|role| {
+                let until = self
+                    .role_expiry_map
+                    .get(&from)
+                    .and_then(|roles| roles.get(role))
+                    .copied();
+                (role.clone(), until)
+            })
+            .collect();
+        let mut transferred = 0;
+        for (role, until) in roles {
+            let has_role = self
+                .user_role_map
+                .get(&to)
+                .is_some_and(|roles| roles.contains(&role))
+                && self.role_active(&to, &role);
+            match until {
+                Some(until) if !has_role => {
+                    self.assign_role_until_id(to.clone(), role, until)?;
+                    transferred += 1;
+                }
+                Some(_) => {}
+                None => {
+                    if self.assign_role_id(to.clone(), role)? && !has_role {
+                        transferred += 1;
+                    }
+                }
+            }
+        }
+        self.clear_user_roles_id(from);
+        Ok(transferred)
+    }
+
+    /// Removes all role assignments and permissions, keeping the allocated memory for reuse.
+    ///
+    /// Settings and rules like limits, constraints and role parents are kept.
+    pub fn clear(&mut self) {
+        self.clear_users();
+        self.clear_permissions();
+    }
+
+    /// Removes all role assignments and emergency overrides, keeping the allocated memory for
+    /// reuse.
+    pub fn clear_users(&mut self) {
+        let hasher = self.user_role_map.hasher().clone();
+        let mut assignments =
+            std::mem::replace(&mut self.user_role_map, HashMap::with_hasher(hasher));
+        for (user, roles) in assignments.drain() {
+            for role in roles {
+                self.record(RbacChange::UnassignRole {
+                    user: user.clone(),
+                    role,
+                });
+            }
+        }
+        self.user_role_map = assignments;
+        self.assigned_by_map.clear();
+        self.role_expiry_map.clear();
+        self.emergency_overrides.clear();
+    }
+
+    /// Removes all permissions from all roles, keeping the allocated memory for reuse.
+    pub fn clear_permissions(&mut self) {
+        self.materialized_map = None;
+        let hasher = self.role_permisson_map.hasher().clone();
+        let mut grants =
+            std::mem::replace(&mut self.role_permisson_map, HashMap::with_hasher(hasher));
+        for (role, permissions) in grants.drain() {
+            for permission in permissions {
+                self.record(RbacChange::RemovePermission {
+                    role: role.clone(),
+                    permission,
+                });
+            }
+        }
+        self.role_permisson_map = grants;
+    }
+
+    /// Removes a permission from every role that has it, e.g. when the permission is retired,
+    /// returning the number of roles that had it.
+    ///
+    /// Permission groups with the permission as a member are left untouched.
+    pub fn remove_permission_everywhere(&mut self, permission: &P) -> usize {
+        self.materialized_map = None;
+        let permission = permission.get_rbac_id();
+        let mut affected = Vec::new();
+        self.role_permisson_map.retain(|role, permissions| {
+            if permissions.remove(&permission) {
+                affected.push(role.clone());
+            }
+            !permissions.is_empty()
+        });
+        let count = affected.len();
+        for role in affected {
+            self.record(RbacChange::RemovePermission {
+                role,
+                permission: permission.clone(),
+            });
+        }
+        count
+    }
+
+    /// Assigns all roles to a user, returning the number of roles the user didn't have before.
+    ///
+    /// Fails like `assign_role` if a role violates a constraint; the roles before it stay
+    /// assigned.
+    pub fn assign_roles(&mut self, user: &U, roles: &[R]) -> Result<usize, InMemoryRbacError> {
+        if roles.is_empty() {
+            return Ok(0);
+        }
+        let user = user.get_rbac_id();
+        if self.has_assignment_constraints() {
+            let mut assigned = 0;
+            for role in roles {
+                if self.assign_role_id(user.clone(), role.get_rbac_id())? {
+                    assigned += 1;
+                }
+            }
+            return Ok(assigned);
+        }
+        let mut reactivated = HashSet::new();
+        if let Some(expiries) = self.role_expiry_map.get_mut(&user) {
+            let now = (self.clock)();
+            for role in roles {
+                let role = role.get_rbac_id();
+                if expiries.remove(&role).is_some_and(|until| until <= now) {
+                    reactivated.insert(role);
+                }
+            }
+            if expiries.is_empty() {
+                self.role_expiry_map.remove(&user);
+            }
+        }
+        let mut assigned = Vec::new();
+        let entry = self.user_role_map.entry(user.clone()).or_default();
+        for role in roles {
+            let role = role.get_rbac_id();
+            let was_expired = reactivated.remove(&role);
+            if entry.insert(role.clone()) || was_expired {
+                assigned.push(role);
+            }
+        }
+        for role in &assigned {
+            self.record(RbacChange::AssignRole {
+                user: user.clone(),
+                role: role.clone(),
+            });
+        }
+        Ok(assigned.len())
+    }
+
+    fn has_assignment_constraints(&self) -> bool {
+        self.strict_roles
+            || !self.exclusive_roles_map.is_empty()
+            || !self.max_users_per_role_map.is_empty()
+            || !self.role_prerequisite_map.is_empty()
+    }
+
+    /// Assigns a role to a user like `assign_role` and records `admin` as the one who assigned it.
+    ///
+    /// If the user already has the role, the original grantor is kept.
+    pub fn assign_role_by(
+        &mut self,
+        admin: &U,
+        user: &U,
+        role: &R,
+    ) -> Result<bool, InMemoryRbacError> {
+        let assigned = self.assign_role(user, role)?;
+        if assigned {
+            self.assigned_by_map
+                .entry(user.get_rbac_id())
+                .or_default()
+                .insert(role.get_rbac_id(), admin.get_rbac_id());
+        }
+        Ok(assigned)
+    }
+
+    /// Assigns a role to a user like `assign_role`, unless an assignment with the same `key` has
+    /// already been made.
+    ///
+    /// Replaying a key doesn't change anything and returns the result of the first call, which
+    /// makes retries of at-least-once deliveries safe. Only the most recent keys are remembered,
+    /// see `set_idempotency_key_capacity`.
+    pub fn assign_role_idempotent(
+        &mut self,
+        user: &U,
+        role: &R,
+        key: &str,
+    ) -> Result<bool, InMemoryRbacError> {
+        if let Some(result) = self.idempotency_keys.get(key) {
+            return Ok(result);
+        }
+        let result = self.assign_role(user, role)?;
+        self.idempotency_keys.insert(key, result);
+        Ok(result)
+    }
+
+    /// Forbids any user to hold both roles at the same time.
+    ///
+    /// Users already holding both roles keep them, but neither role can be assigned to a user
+    /// holding the other one anymore.
+    pub fn add_mutually_exclusive_roles(&mut self, a: &R, b: &R) {
+        let (a, b) = (a.get_rbac_id(), b.get_rbac_id());
+        if a == b {
+            return;
+        }
+        self.exclusive_roles_map
+            .entry(a.clone())
+            .or_default()
+            .insert(b.clone());
+        self.exclusive_roles_map.entry(b).or_default().insert(a);
+    }
+
+    /// Limits the number of users that may hold the role.
+    ///
+    /// Users already holding the role keep it, even if there are more than `limit` of them.
+    pub fn set_max_users_per_role(&mut self, role: &R, limit: usize) {
+        self.max_users_per_role_map
+            .insert(role.get_rbac_id(), limit);
+    }
+
+    /// Only allows assigning roles that have at least one permission if `strict` is `true`.
+    pub fn set_strict_roles(&mut self, strict: bool) {
+        self.strict_roles = strict;
+    }
+
+    /// Only allows assigning `role` to users that already hold `prerequisite`, e.g. a supervisor
+    /// must be an agent first.
+    ///
+    /// A role may have multiple prerequisites, all of which are required. Unassigning a
+    /// prerequisite later doesn't unassign the role.
+    pub fn set_role_prerequisite(&mut self, role: &R, prerequisite: &R) {
+        self.role_prerequisite_map
+            .entry(role.get_rbac_id())
+            .or_default()
+            .insert(prerequisite.get_rbac_id());
+    }
+
+    /// Removes `prerequisite` from the prerequisites of `role`, returning whether it was one.
+    pub fn remove_role_prerequisite(&mut self, role: &R, prerequisite: &R) -> bool {
+        let role = role.get_rbac_id();
+        let removed = match self.role_prerequisite_map.get_mut(&role) {
+            Some(prerequisites) => prerequisites.remove(&prerequisite.get_rbac_id()),
+            None => false,
+        };
+        if self
+            .role_prerequisite_map
+            .get(&role)
+            .is_some_and(HashSet::is_empty)
+        {
+            self.role_prerequisite_map.remove(&role);
+        }
+        removed
+    }
+
+    /// Checks whether assigning the role to the user satisfies every registered constraint,
+    /// without assigning it.
+    ///
+    /// Returns the first violated constraint. `assign_role` fails in exactly these cases, with
+    /// `InMemoryRbacError::MissingPrerequisiteRole` or `InMemoryRbacError::ConstraintViolated`.
+    pub fn can_assign(&self, user: &U, role: &R) -> Result<(), ConstraintViolation> {
+        self.check_assign_id(&user.get_rbac_id(), &role.get_rbac_id())
+    }
+
+    fn check_assign_id(&self, user: &U::Id, role: &R::Id) -> Result<(), ConstraintViolation> {
+        let roles = self.user_role_map.get(user);
+        let has_role = |role: &R::Id| {
+            roles.is_some_and(|roles| roles.contains(role)) && self.role_active(user, role)
+        };
+        if has_role(role) {
+            return Ok(());
+        }
+        if self.strict_roles
+            && !self.role_permisson_map.contains_key(role)
+            && !self.role_parent_map.contains_key(role)
+        {
+            return Err(ConstraintViolation::UnknownRole);
+        }
+        if let Some(prerequisites) = self.role_prerequisite_map.get(role) {
+            if !prerequisites.iter().all(has_role) {
+                return Err(ConstraintViolation::MissingPrerequisiteRole);
+            }
+        }
+        if let Some(exclusive) = self.exclusive_roles_map.get(role) {
+            if exclusive.iter().any(has_role) {
+                return Err(ConstraintViolation::MutuallyExclusiveRole);
+            }
+        }
+        if let Some(&limit) = self.max_users_per_role_map.get(role) {
+            let holders = self
+                .user_role_map
+                .iter()
+                .filter(|(user, roles)| roles.contains(role) && self.role_active(user, role))
+                .count();
+            if holders >= limit {
+                return Err(ConstraintViolation::MaxUsersPerRoleExceeded);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the id of the user who assigned the role to the user, if it was recorded with
+    /// `assign_role_by`.
+    pub fn role_assigned_by(&self, user: &U, role: &R) -> Option<U::Id> {
+        self.assigned_by_map
+            .get(&user.get_rbac_id())?
+            .get(&role.get_rbac_id())
+            .cloned()
+    }
+
+    /// Collects the roles of a user and the union of their permissions into a response ready to
+    /// be serialized, e.g. for a `/me/permissions` endpoint.
+    #[allow(clippy::type_complexity)]
+    pub fn capabilities_response(
+        &self,
+        user: &U,
+    ) -> Result<CapabilitiesResponse<U::Id, R::Id, P::Id>, InMemoryRbacError> {
+        let user_id = user.get_rbac_id();
+        let roles = self.active_roles(&user_id).cloned().collect();
+        Ok(CapabilitiesResponse {
+            permissions: self.permission_set(&user_id).into_iter().cloned().collect(),
+            user_id,
+            roles,
+        })
+    }
+
+    /// Returns the permissions held by exactly one of the two users.
+    pub fn permission_symmetric_difference(
+        &self,
+        a: &U,
+        b: &U,
+    ) -> Result<HashSet<P::Id>, InMemoryRbacError> {
+        let a = self.permission_set(&a.get_rbac_id());
+        let b = self.permission_set(&b.get_rbac_id());
+        Ok(a.symmetric_difference(&b).map(|&p| p.clone()).collect())
+    }
+
+    /// Checks whether the user has the permission, treating a granted permission ending in `*` as
+    /// a prefix that matches every permission starting with it.
+    ///
+    /// For example, `billing.*` matches `billing.invoice.read` and a bare `*` matches every
+    /// permission. A permission matched by a prefix goes through the same checks as an exact
+    /// match: it isn't matched if it is denied to the user with `deny_permission`, if the user
+    /// lacks its minimum number of roles or if one of its prerequisites isn't matched.
+    pub fn user_has_permission_glob(
+        &self,
+        user: &U,
+        permission: &P,
+    ) -> Result<bool, InMemoryRbacError>
+    where
+        P::Id: AsRef<str>,
+    {
+        let user = user.get_rbac_id();
+        let permission = permission.get_rbac_id();
+        if self.has_permission_id(&user, &permission) {
+            return Ok(true);
+        }
+        let granted = self.permission_set(&user);
+        let matches = |requested: &P::Id| {
+            granted.contains(requested)
+                || granted
+                    .iter()
+                    .any(|granted| match granted.as_ref().strip_suffix('*') {
+                        Some(prefix) => requested.as_ref().starts_with(prefix),
+                        None => false,
+                    })
+        };
+        let mut current = &permission;
+        loop {
+            if self.is_denied(&user, current)
+                || !matches(current)
+                || !self.has_min_roles(&user, current, None)
+            {
+                return Ok(false);
+            }
+            match self.prerequisite_map.get(current) {
+                Some(prerequisite) => current = prerequisite,
+                None => return Ok(true),
+            }
+        }
+    }
+
+    /// Checks whether user `b` has every effective permission of user `a`, e.g. to verify that a
+    /// restricted account is really less privileged than another one.
+    ///
+    /// A user without any permissions has a subset of everyone's access.
+    pub fn access_subset_of(&self, a: &U, b: &U) -> Result<bool, InMemoryRbacError> {
+        let b = b.get_rbac_id();
+        Ok(self
+            .permission_set(&a.get_rbac_id())
+            .into_iter()
+            .all(|p| self.has_permission_id(&b, p)))
+    }
+
+    /// Returns the effective permissions of user `a` that user `b` doesn't have, e.g. to show
+    /// what a manager can do that an agent can't.
+    ///
+    /// A user without roles simply has no permissions.
+    pub fn permission_difference(&self, a: &U, b: &U) -> Result<HashSet<P::Id>, InMemoryRbacError> {
+        let b = b.get_rbac_id();
+        Ok(self
+            .permission_set(&a.get_rbac_id())
+            .into_iter()
+            .filter(|p| !self.has_permission_id(&b, p))
+            .cloned()
+            .collect())
+    }
+
+    /// Checks for each of the permissions whether the user has it, in the order of
+    /// `permissions`.
+    ///
+    /// The effective permissions of the user are resolved only once for all checks.
+    pub fn user_permissions_check(
+        &self,
+        user: &U,
+        permissions: &[P],
+    ) -> Result<Vec<bool>, InMemoryRbacError> {
+        let user = user.get_rbac_id();
+        if self.has_emergency_override(&user) {
+            return Ok(vec![true; permissions.len()]);
+        }
+        let effective = self.permission_set(&user);
+        Ok(permissions
+            .iter()
+            .map(|permission| effective.contains(&permission.get_rbac_id()))
+            .collect())
+    }
+
+    /// Suggests roles the user doesn't have yet, but whose permissions the user mostly has
+    /// already through other roles.
+    ///
+    /// A role is scored by the number of permissions it would add. Roles that would add at most
+    /// half of their permissions are returned, sorted by ascending score. Roles with a score of
+    /// `0` come first; assigning them would be redundant.
+    pub fn recommend_roles(&self, user: &U) -> Result<Vec<R::Id>, InMemoryRbacError> {
+        let user = user.get_rbac_id();
+        let roles: HashSet<&R::Id> = self.active_roles(&user).collect();
+        if roles.is_empty() {
+            return Err(InMemoryRbacError::UserHasNoRoles);
+        }
+        let effective = self.permission_set(&user);
+        let candidates: HashSet<&R::Id> = self
+            .role_permisson_map
+            .keys()
+            .chain(self.role_parent_map.keys())
+            .filter(|role| !roles.contains(role))
+            .collect();
+        let mut scored: Vec<(&R::Id, usize)> = candidates
+            .into_iter()
+            .filter_map(|role| {
+                let permissions = self.role_permission_set(role);
+                let new = permissions
+                    .iter()
+                    .filter(|p| !effective.contains(*p))
+                    .count();
+                if new * 2 <= permissions.len() {
+                    Some((role, new))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        scored.sort_by_key(|&(_, new)| new);
+        Ok(scored.into_iter().map(|(role, _)| role.clone()).collect())
+    }
+
+    /// Creates an iterator over the `Id`s of the effective permissions of a user, yielding each
+    /// permission once even if multiple roles grant it.
+    ///
+    /// Like `RbacIterators::iter_user_role_ids`, this fails with
+    /// `InMemoryRbacError::UserHasNoRoles` if the user has no roles.
+    pub fn iter_user_permission_ids(
+        &self,
+        user: &U,
+    ) -> Result<impl Iterator<Item = P::Id> + '_, InMemoryRbacError> {
+        let user = user.get_rbac_id();
+        if self.active_roles(&user).next().is_none() {
+            return Err(InMemoryRbacError::UserHasNoRoles);
+        }
+        Ok(self.permission_set(&user).into_iter().cloned())
+    }
+
+    /// Groups the users with identical effective permissions, e.g. to find candidates for a shared
+    /// role.
+    ///
+    /// Only users with at least one role are considered and users without a duplicate are
+    /// omitted. The order of the groups and of the users in a group is unspecified.
+    #[allow(clippy::type_complexity)]
+    pub fn duplicate_access_groups(&self) -> Vec<Vec<U::Id>> {
+        let mut buckets: HashMap<u64, Vec<(HashSet<&P::Id>, Vec<&U::Id>)>> = HashMap::new();
+        for user in self.user_role_map.keys() {
+            let permissions = self.permission_set(user);
+            let key = permissions.iter().fold(0u64, |key, p| {
+                let mut hasher = DefaultHasher::new();
+                p.hash(&mut hasher);
+                key.wrapping_add(hasher.finish())
+            });
+            let bucket = buckets.entry(key).or_default();
+            match bucket.iter_mut().find(|(other, _)| *other == permissions) {
+                Some((_, users)) => users.push(user),
+                None => bucket.push((permissions, vec![user])),
+            }
+        }
+        buckets
+            .into_values()
+            .flatten()
+            .filter(|(_, users)| users.len() > 1)
+            .map(|(_, users)| users.into_iter().cloned().collect())
+            .collect()
+    }
+
+    /// Resolves the effective permissions of the user like `iter_user_permission_ids`, but
+    /// visits at most `budget` roles and permissions, e.g. so that a pathological hierarchy can't
+    /// stall a request.
+    ///
+    /// Returns the permissions found so far and `true` if the budget ran out before all of them
+    /// were visited. Fails with `InMemoryRbacError::UserHasNoRoles` if the user has no roles.
+    pub fn resolve_permissions_bounded(
+        &self,
+        user: &U,
+        budget: usize,
+    ) -> Result<(HashSet<P::Id>, bool), InMemoryRbacError> {
+        let user = user.get_rbac_id();
+        let mut roles: Vec<&R::Id> = self.active_roles(&user).collect();
+        if roles.is_empty() {
+            return Err(InMemoryRbacError::UserHasNoRoles);
+        }
+        let mut remaining = budget;
+        let mut truncated = false;
+        let role_count = roles.len();
+        let mut visited_roles = HashSet::new();
+        let mut pending: Vec<&P::Id> = Vec::new();
+        let mut permissions: HashSet<&P::Id> = HashSet::new();
+        loop {
+            if let Some(permission) = pending.pop() {
+                if permissions.contains(permission) {
+                    continue;
+                }
+                if remaining == 0 {
+                    truncated = true;
+                    break;
+                }
+                remaining -= 1;
+                permissions.insert(permission);
+                pending.extend(
+                    self.permission_group_map
+                        .get(permission)
+                        .into_iter()
+                        .flatten(),
+                );
+            } else if let Some(role) = roles.pop() {
+                if visited_roles.contains(role) {
+                    continue;
+                }
+                if remaining == 0 {
+                    truncated = true;
+                    break;
+                }
+                remaining -= 1;
+                visited_roles.insert(role);
+                pending.extend(self.role_permisson_map.get(role).into_iter().flatten());
+                roles.extend(self.role_parent_map.get(role).into_iter().flatten());
+            } else {
+                break;
+            }
+        }
+        self.retain_effective(&user, role_count, &mut permissions);
+        Ok((permissions.into_iter().cloned().collect(), truncated))
+    }
+
+    /// Collects the effective permissions of all roles of the user.
+    fn permission_set(&self, user: &U::Id) -> HashSet<&P::Id> {
+        let roles: Vec<&R::Id> = self.active_roles(user).collect();
+        self.roles_permission_set(user, &roles)
+    }
+
+    /// Collects the effective permissions the user would have with exactly the given roles.
+    fn roles_permission_set(&self, user: &U::Id, roles: &[&R::Id]) -> HashSet<&P::Id> {
+        let mut permissions: HashSet<&P::Id> = HashSet::new();
+        for role in roles {
+            self.collect_role_permissions(role, &mut permissions);
+        }
+        self.retain_effective(user, roles.len(), &mut permissions);
+        permissions
+    }
+
+    /// Removes the permissions granted by `role_count` roles of the user that are denied to the
+    /// user or lack their minimum number of roles or their prerequisites.
+    fn retain_effective(&self, user: &U::Id, role_count: usize, permissions: &mut HashSet<&P::Id>) {
+        if let Some(denied) = self.user_denied_map.get(user) {
+            permissions.retain(|p| !denied.contains(*p));
+        }
+        if !self.min_roles_map.is_empty() {
+            permissions.retain(|p| match self.min_roles_map.get(*p) {
+                Some(&min_roles) => role_count >= min_roles,
+                None => true,
+            });
+        }
+        if !self.prerequisite_map.is_empty() {
+            let granted = permissions.clone();
+            permissions.retain(|&p| {
+                let mut current = p;
+                while let Some(prerequisite) = self.prerequisite_map.get(current) {
+                    if !granted.contains(prerequisite) {
+                        return false;
+                    }
+                    current = prerequisite;
+                }
+                true
+            });
+        }
+    }
+
+    /// Returns the permissions of each role of a user, including inherited ones, keyed by the id
+    /// of the role.
+    ///
+    /// Roles of the user that have no permissions map to an empty `Vec`.
+    #[allow(clippy::type_complexity)]
+    pub fn user_permissions_by_role(
+        &self,
+        user: &U,
+    ) -> Result<HashMap<R::Id, Vec<P::Id>>, InMemoryRbacError> {
+        let user = user.get_rbac_id();
+        Ok(self
+            .active_roles(&user)
+            .map(|r| {
+                let permissions = self.role_permission_set(r).into_iter().cloned().collect();
+                (r.clone(), permissions)
+            })
+            .collect())
+    }
+
+    /// Copies the permissions of role `from` for which `filter` returns `true` to role `to`.
+    ///
+    /// Returns the number of permissions `to` didn't have before. If a limit was set with
+    /// `set_max_permissions_per_role`, permissions that would exceed it are skipped.
+    pub fn clone_role_filtered<F: Fn(&P::Id) -> bool>(
+        &mut self,
+        from: &R,
+        to: &R,
+        filter: F,
+    ) -> usize {
+        self.materialized_map = None;
+        let permissions: Vec<P::Id> = match self.role_permisson_map.get(&from.get_rbac_id()) {
+            Some(val) => val.iter().filter(|p| filter(p)).cloned().collect(),
+            None => return 0,
+        };
+        let limit = self.max_permissions_per_role.unwrap_or(usize::MAX);
+        let target = self.role_permisson_map.entry(to.get_rbac_id()).or_default();
+        let mut added = Vec::new();
+        for p in permissions {
+            if target.len() < limit && target.insert(p.clone()) {
+                added.push(p);
+            }
+        }
+        if target.is_empty() {
+            self.role_permisson_map.remove(&to.get_rbac_id());
+        }
+        let count = added.len();
+        for permission in added {
+            self.record(RbacChange::AddPermission {
+                role: to.get_rbac_id(),
+                permission,
+            });
+        }
+        count
+    }
+
+    /// Returns every granted permission with the number of roles granting it, most granted first.
+    ///
+    /// The order of permissions granted by the same number of roles is unspecified.
+    pub fn permission_popularity(&self) -> Vec<(P::Id, usize)> {
+        let mut counts: HashMap<&P::Id, usize> = HashMap::new();
+        for p in self.role_permisson_map.values().flatten() {
+            *counts.entry(p).or_default() += 1;
+        }
+        let mut popularity: Vec<_> = counts.into_iter().map(|(p, n)| (p.clone(), n)).collect();
+        popularity.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+        popularity
+    }
+
+    /// Returns every assigned role with the number of users holding it, most assigned first.
+    ///
+    /// The order of roles held by the same number of users is unspecified.
+    pub fn role_fanout(&self) -> Vec<(R::Id, usize)> {
+        let mut counts: HashMap<&R::Id, usize> = HashMap::new();
+        for user in self.user_role_map.keys() {
+            for r in self.active_roles(user) {
+                *counts.entry(r).or_default() += 1;
+            }
+        }
+        let mut fanout: Vec<_> = counts.into_iter().map(|(r, n)| (r.clone(), n)).collect();
+        fanout.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+        fanout
+    }
+
+    /// Checks whether role `a` has every permission that role `b` has.
+    ///
+    /// A role without any permissions is a subset of every role.
+    pub fn role_is_superset_of(&self, a: &R, b: &R) -> bool {
+        let a = a.get_rbac_id();
+        self.role_permission_set(&b.get_rbac_id())
+            .into_iter()
+            .all(|p| self.role_has_permission_id(&a, p))
+    }
+
+    /// Creates an iterator over the ids of all users with at least one role that hasn't expired.
+    ///
+    /// The order of the users is unspecified.
+    pub fn iter_users(&self) -> impl Iterator<Item = U::Id> + '_ {
+        self.user_role_map
+            .keys()
+            .filter(move |user| self.active_roles(user).next().is_some())
+            .cloned()
+    }
+
+    /// Creates an iterator over the ids of all roles with at least one permission of their own.
+    ///
+    /// The order of the roles is unspecified.
+    pub fn iter_roles(&self) -> impl Iterator<Item = R::Id> + '_ {
+        self.role_permisson_map.keys().cloned()
+    }
+
+    /// Creates an iterator over the ids of all users with at least one role that match
+    /// `predicate`.
+    ///
+    /// The order of the users is unspecified.
+    pub fn iter_users_where<'a, F>(&'a self, predicate: F) -> impl Iterator<Item = U::Id> + 'a
+    where
+        F: Fn(&U::Id) -> bool + 'a,
+    {
+        self.user_role_map
+            .keys()
+            .filter(move |user| self.active_roles(user).next().is_some() && predicate(user))
+            .cloned()
+    }
+
+    /// Creates an iterator over the ids of all users that have the role.
+    ///
+    /// The order of the users is unspecified.
+    pub fn iter_users_with_role(&self, role: &R) -> impl Iterator<Item = U::Id> + '_ {
+        let role = role.get_rbac_id();
+        self.user_role_map
+            .iter()
+            .filter(move |(user, roles)| roles.contains(&role) && self.role_active(user, &role))
+            .map(|(user, _)| user.clone())
+    }
+
+    /// Creates an iterator over the ids of all roles that have the permission.
+    ///
+    /// The order of the roles is unspecified.
+    pub fn iter_roles_with_permission(&self, permission: &P) -> impl Iterator<Item = R::Id> + '_ {
+        let permission = permission.get_rbac_id();
+        let inheriting = self
+            .role_parent_map
+            .keys()
+            .filter(move |role| !self.role_permisson_map.contains_key(*role));
+        self.role_permisson_map
+            .keys()
+            .chain(inheriting)
+            .filter(move |role| self.role_has_permission_id(role, &permission))
+            .cloned()
+    }
+
+    /// Returns all role assignments whose role isn't part of `known_roles`, e.g. because the role
+    /// has been deleted in an external system.
+    pub fn dangling_assignments(&self, known_roles: &[R]) -> Vec<(U::Id, R::Id)> {
+        let known: HashSet<R::Id> = known_roles.iter().map(|r| r.get_rbac_id()).collect();
+        self.user_role_map
+            .iter()
+            .flat_map(|(user, roles)| {
+                roles
+                    .iter()
+                    .filter(|r| !known.contains(r))
+                    .map(move |r| (user.clone(), r.clone()))
+            })
+            .collect()
+    }
+
+    /// Returns the users that would lose at least one of the `required` permissions if the role
+    /// were removed from them.
+    pub fn impact_of_removing_role(
+        &self,
+        role: &R,
+        required: &[P],
+    ) -> Result<Vec<U::Id>, InMemoryRbacError> {
+        let role = role.get_rbac_id();
+        let required: Vec<P::Id> = required.iter().map(|p| p.get_rbac_id()).collect();
+        Ok(self
+            .user_role_map
+            .iter()
+            .filter(|(user, roles)| roles.contains(&role) && self.role_active(user, &role))
+            .filter(|(user, _)| {
+                required.iter().any(|p| {
+                    self.has_permission_id(user, p)
+                        && !self.has_permission_id_without(user, p, Some(&role))
+                })
+            })
+            .map(|(user, _)| user.clone())
+            .collect())
+    }
+
+    /// Returns all permission grants whose permission isn't part of `known`, e.g. because the
+    /// permission has been removed from the application.
+    pub fn roles_granting_unknown_permissions(&self, known: &[P]) -> Vec<(R::Id, P::Id)> {
+        let known: HashSet<P::Id> = known.iter().map(|p| p.get_rbac_id()).collect();
+        self.role_permisson_map
+            .iter()
+            .flat_map(|(role, permissions)| {
+                permissions
+                    .iter()
+                    .filter(|p| !known.contains(p))
+                    .map(move |p| (role.clone(), p.clone()))
+            })
+            .collect()
+    }
+
+    /// Returns a matrix with one row per role and one column per permission, in the given order,
+    /// that tells whether the role grants the permission.
+    pub fn to_bitmatrix(&self, roles: &[R], permissions: &[P]) -> Vec<Vec<bool>> {
+        let permissions: Vec<P::Id> = permissions.iter().map(|p| p.get_rbac_id()).collect();
+        roles
+            .iter()
+            .map(|r| {
+                let r = r.get_rbac_id();
+                permissions
+                    .iter()
+                    .map(|p| self.role_has_permission_id(&r, p))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Builds the smallest model that yields the same `user_has_permission` result for the user
+    /// and permission as `self`, e.g. to attach it to a bug report.
+    ///
+    /// The model only contains the roles of the user that grant the permission or one of its
+    /// prerequisites, and only those permissions and the rules that apply to them. If one of
+    /// them requires a minimum number of roles, all roles of the user are kept. Expired roles
+    /// are left out, while the expiry times of the kept roles, the denials of the permissions
+    /// and the emergency override of the user are copied.
+    pub fn minimal_model_for(&self, user: &U, permission: &P) -> InMemoryRbac<U, R, P, S> {
+        let user = user.get_rbac_id();
+        let mut relevant = vec![permission.get_rbac_id()];
+        while let Some(prerequisite) = self.prerequisite_map.get(&relevant[relevant.len() - 1]) {
+            relevant.push(prerequisite.clone());
+        }
+
+        let mut model = InMemoryRbac::with_hasher(self.user_role_map.hasher().clone());
+        model.clock = self.clock;
+        for pair in relevant.windows(2) {
+            model
+                .prerequisite_map
+                .insert(pair[0].clone(), pair[1].clone());
+        }
+        for p in &relevant {
+            if let Some(&min_roles) = self.min_roles_map.get(p) {
+                model.min_roles_map.insert(p.clone(), min_roles);
+            }
+        }
+        if let Some(denied) = self.user_denied_map.get(&user) {
+            let denied: HashSet<P::Id> = relevant
+                .iter()
+                .filter(|p| denied.contains(p))
+                .cloned()
+                .collect();
+            if !denied.is_empty() {
+                model.user_denied_map.insert(user.clone(), denied);
+            }
+        }
+        if let Some(&until) = self.emergency_overrides.get(&user) {
+            model.emergency_overrides.insert(user.clone(), until);
+        }
+        let keep_all_roles = !model.min_roles_map.is_empty();
+        for role in self.active_roles(&user) {
+            let granted: HashSet<P::Id> = relevant
+                .iter()
+                .filter(|p| self.role_has_permission_id(role, p))
+                .cloned()
+                .collect();
+            if granted.is_empty() {
+                if keep_all_roles {
+                    model
+                        .user_role_map
+                        .entry(user.clone())
+                        .or_default()
+                        .insert(role.clone());
+                }
+                continue;
+            }
+            model
+                .user_role_map
+                .entry(user.clone())
+                .or_default()
+                .insert(role.clone());
+            model.role_permisson_map.insert(role.clone(), granted);
+        }
+        if let (Some(roles), Some(expiries)) = (
+            model.user_role_map.get(&user),
+            self.role_expiry_map.get(&user),
+        ) {
+            let expiries: HashMap<R::Id, SystemTime> = expiries
+                .iter()
+                .filter(|(role, _)| roles.contains(*role))
+                .map(|(role, &until)| (role.clone(), until))
+                .collect();
+            if !expiries.is_empty() {
+                model.role_expiry_map.insert(user.clone(), expiries);
+            }
+        }
+        model
+    }
+
+    /// Computes a hash of all role assignments and their expiry times, permission grants, role
+    /// parents, denied permissions and the rules about when permissions are effective.
+    ///
+    /// The fingerprint doesn't depend on the order in which the model was built, so two models
+    /// with the same contents have the same fingerprint. It's only stable within one build of
+    /// this crate and shouldn't be persisted.
+    pub fn fingerprint(&self) -> u64 {
+        fn hash_pair<A: Hash, B: Hash>(tag: u8, a: &A, b: &B) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            tag.hash(&mut hasher);
+            a.hash(&mut hasher);
+            b.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let mut fingerprint = 0u64;
+        for (user, roles) in &self.user_role_map {
+            for role in roles {
+                fingerprint = fingerprint.wrapping_add(hash_pair(0, user, role));
+            }
+        }
+        for (role, permissions) in &self.role_permisson_map {
+            for permission in permissions {
+                fingerprint = fingerprint.wrapping_add(hash_pair(1, role, permission));
+            }
+        }
+        for (permission, prerequisite) in &self.prerequisite_map {
+            fingerprint = fingerprint.wrapping_add(hash_pair(2, permission, prerequisite));
+        }
+        for (permission, min_roles) in &self.min_roles_map {
+            fingerprint = fingerprint.wrapping_add(hash_pair(3, permission, min_roles));
+        }
+        for (role, parents) in &self.role_parent_map {
+            for parent in parents {
+                fingerprint = fingerprint.wrapping_add(hash_pair(4, role, parent));
+            }
+        }
+        for (group, members) in &self.permission_group_map {
+            for member in members {
+                fingerprint = fingerprint.wrapping_add(hash_pair(5, group, member));
+            }
+        }
+        for (user, permissions) in &self.user_denied_map {
+            for permission in permissions {
+                fingerprint = fingerprint.wrapping_add(hash_pair(6, user, permission));
+            }
+        }
+        for (user, expiries) in &self.role_expiry_map {
+            for (role, until) in expiries {
+                fingerprint = fingerprint.wrapping_add(hash_pair(7, &(user, role), until));
+            }
+        }
+        fingerprint
+    }
+
+    /// Lists every permission of every role of every user as `(user, role, permission)`, e.g.
+    /// for an offline review.
+    ///
+    /// Inherited permissions and members of permission groups are included; expired assignments
+    /// aren't. Rules like prerequisites and denials aren't applied, so a listed permission isn't
+    /// necessarily effective. The order of the rows is unspecified.
+    pub fn export_matrix(&self) -> Vec<(U::Id, R::Id, P::Id)> {
+        let mut rows = Vec::new();
+        for user in self.user_role_map.keys() {
+            for role in self.active_roles(user) {
+                rows.extend(
+                    self.role_permission_set(role)
+                        .into_iter()
+                        .map(|p| (user.clone(), role.clone(), p.clone())),
+                );
+            }
+        }
+        rows
+    }
+
+    /// Renders the model as a Graphviz DOT graph, e.g. to visualize it with `dot -Tsvg`.
+    ///
+    /// Users, roles and permissions are drawn as blue ellipses, green boxes and orange notes.
+    /// Assignments and permissions of roles are solid edges, role parents dashed edges and
+    /// members of permission groups dotted edges. Expired assignments are omitted.
+    pub fn to_dot(&self) -> String
+    where
+        U::Id: fmt::Display,
+        R::Id: fmt::Display,
+        P::Id: fmt::Display,
+    {
+        fn escape(id: &dyn fmt::Display) -> String {
+            id.to_string().replace('\\', "\\\\").replace('"', "\\\"")
+        }
+
+        let mut users = BTreeSet::new();
+        let mut roles = BTreeSet::new();
+        let mut permissions = BTreeSet::new();
+        let mut edges = BTreeSet::new();
+        for user in self.user_role_map.keys() {
+            let user_node = escape(user);
+            for role in self.active_roles(user) {
+                let role = escape(role);
+                edges.insert(format!("\"user:{}\" -> \"role:{}\";", user_node, role));
+                roles.insert(role);
+            }
+            users.insert(user_node);
+        }
+        for (role, granted) in &self.role_permisson_map {
+            let role = escape(role);
+            for permission in granted {
+                let permission = escape(permission);
+                edges.insert(format!(
+                    "\"role:{}\" -> \"permission:{}\";",
+                    role, permission
+                ));
+                permissions.insert(permission);
+            }
+            roles.insert(role);
+        }
+        for (role, parents) in &self.role_parent_map {
+            let role = escape(role);
+            for parent in parents {
+                let parent = escape(parent);
+                edges.insert(format!(
+                    "\"role:{}\" -> \"role:{}\" [style=dashed];",
+                    role, parent
+                ));
+                roles.insert(parent);
+            }
+            roles.insert(role);
+        }
+        for (group, members) in &self.permission_group_map {
+            let group = escape(group);
+            for member in members {
+                let member = escape(member);
+                edges.insert(format!(
+                    "\"permission:{}\" -> \"permission:{}\" [style=dotted];",
+                    group, member
+                ));
+                permissions.insert(member);
+            }
+            permissions.insert(group);
+        }
+
+        let mut dot = String::from("digraph rbac {\n");
+        for user in users {
+            dot.push_str(&format!(
+                "    \"user:{0}\" [label=\"{0}\", shape=ellipse, color=blue];\n",
+                user
+            ));
+        }
+        for role in roles {
+            dot.push_str(&format!(
+                "    \"role:{0}\" [label=\"{0}\", shape=box, color=darkgreen];\n",
+                role
+            ));
+        }
+        for permission in permissions {
+            dot.push_str(&format!(
+                "    \"permission:{0}\" [label=\"{0}\", shape=note, color=orange];\n",
+                permission
+            ));
+        }
+        for edge in edges {
+            dot.push_str("    ");
+            dot.push_str(&edge);
+            dot.push('\n');
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Exports the permissions of all roles, without any role assignments.
+    pub fn export_catalog(&self) -> CatalogExport<R::Id, P::Id> {
+        CatalogExport {
+            roles: self
+                .role_permisson_map
+                .iter()
+                .map(|(role, permissions)| (role.clone(), permissions.clone()))
+                .collect(),
+        }
+    }
+
+    /// Replaces the permissions of all roles with those in `catalog`.
+    ///
+    /// Role assignments are left untouched.
+    pub fn import_catalog(&mut self, catalog: CatalogExport<R::Id, P::Id>) {
+        self.materialized_map = None;
+        let mut roles = HashMap::with_capacity_and_hasher(
+            catalog.roles.len(),
+            self.role_permisson_map.hasher().clone(),
+        );
+        roles.extend(
+            catalog
+                .roles
+                .into_iter()
+                .filter(|(_, permissions)| !permissions.is_empty()),
+        );
+        let mut old = std::mem::replace(&mut self.role_permisson_map, roles);
+
+        if self.audit_log.is_some() {
+            let mut changes = Vec::new();
+            for (role, permissions) in &self.role_permisson_map {
+                let old_permissions = old.remove(role).unwrap_or_default();
+                for p in old_permissions.difference(permissions) {
+                    changes.push(RbacChange::RemovePermission {
+                        role: role.clone(),
+                        permission: p.clone(),
+                    });
+                }
+                for p in permissions.difference(&old_permissions) {
+                    changes.push(RbacChange::AddPermission {
+                        role: role.clone(),
+                        permission: p.clone(),
+                    });
+                }
+            }
+            for (role, permissions) in old {
+                for permission in permissions {
+                    changes.push(RbacChange::RemovePermission {
+                        role: role.clone(),
+                        permission,
+                    });
+                }
+            }
+            for action in changes {
+                self.record(action);
+            }
+        }
+    }
+
+    /// Captures the current role assignments and permissions, to be compared to a later state
+    /// with `diff`.
+    pub fn snapshot(&self) -> Snapshot<U::Id, R::Id, P::Id> {
+        Snapshot {
+            assignments: self
+                .user_role_map
+                .iter()
+                .map(|(user, roles)| (user.clone(), roles.clone()))
+                .collect(),
+            grants: self
+                .role_permisson_map
+                .iter()
+                .map(|(role, permissions)| (role.clone(), permissions.clone()))
+                .collect(),
+        }
+    }
+
+    /// Lists the role assignments and permissions that have been added or removed since
+    /// `previous` was captured.
+    ///
+    /// The order of the changes in each category is unspecified.
+    pub fn diff(&self, previous: &Snapshot<U::Id, R::Id, P::Id>) -> RbacDiff<U::Id, R::Id, P::Id> {
+        let mut diff = RbacDiff {
+            assigned: Vec::new(),
+            unassigned: Vec::new(),
+            added_permissions: Vec::new(),
+            removed_permissions: Vec::new(),
+        };
+        let empty_roles = HashSet::new();
+        for (user, roles) in &self.user_role_map {
+            let old = previous.assignments.get(user).unwrap_or(&empty_roles);
+            diff.assigned
+                .extend(roles.difference(old).map(|r| (user.clone(), r.clone())));
+        }
+        for (user, old) in &previous.assignments {
+            let roles = self.user_role_map.get(user).unwrap_or(&empty_roles);
+            diff.unassigned
+                .extend(old.difference(roles).map(|r| (user.clone(), r.clone())));
+        }
+        let empty_permissions = HashSet::new();
+        for (role, permissions) in &self.role_permisson_map {
+            let old = previous.grants.get(role).unwrap_or(&empty_permissions);
+            diff.added_permissions.extend(
+                permissions
+                    .difference(old)
+                    .map(|p| (role.clone(), p.clone())),
+            );
+        }
+        for (role, old) in &previous.grants {
+            let permissions = self
+                .role_permisson_map
+                .get(role)
+                .unwrap_or(&empty_permissions);
+            diff.removed_permissions.extend(
+                old.difference(permissions)
+                    .map(|p| (role.clone(), p.clone())),
+            );
+        }
+        diff
+    }
+
+    /// Adds all role assignments and permissions of `other` to this model, e.g. to combine
+    /// role definitions spread across modules.
+    ///
+    /// The roles of a user and the permissions of a role are combined with those already in this
+    /// model. Assignments keep their expiry time, an assignment present in both models lasts as
+    /// long as the longer of the two. Constraints aren't checked, and settings and rules of
+    /// `other` are discarded.
+    pub fn merge(&mut self, mut other: InMemoryRbac<U, R, P, S>) {
+        self.materialized_map = None;
+        for (user, roles) in other.user_role_map {
+            let mut expiries = other.role_expiry_map.remove(&user).unwrap_or_default();
+            let entry = self.user_role_map.entry(user.clone()).or_default();
+            let (added, existing): (Vec<R::Id>, Vec<R::Id>) =
+                roles.into_iter().partition(|r| entry.insert(r.clone()));
+            for role in added {
+                match expiries.remove(&role) {
+                    Some(until) => {
+                        self.role_expiry_map
+                            .entry(user.clone())
+                            .or_default()
+                            .insert(role.clone(), until);
+                        self.record(RbacChange::AssignRoleUntil {
+                            user: user.clone(),
+                            role,
+                            until,
+                        });
+                    }
+                    None => self.record(RbacChange::AssignRole {
+                        user: user.clone(),
+                        role,
+                    }),
+                }
+            }
+            for role in existing {
+                match expiries.remove(&role) {
+                    Some(until) => {
+                        if let Some(current) = self
+                            .role_expiry_map
+                            .get_mut(&user)
+                            .and_then(|roles| roles.get_mut(&role))
+                        {
+                            if *current < until {
+                                *current = until;
+                            }
+                        }
+                    }
+                    None => self.remove_role_expiry(&user, &role),
+                }
+            }
+        }
+        for (role, permissions) in other.role_permisson_map {
+            let entry = self.role_permisson_map.entry(role.clone()).or_default();
+            let added: Vec<P::Id> = permissions
+                .into_iter()
+                .filter(|p| entry.insert(p.clone()))
+                .collect();
+            for permission in added {
+                self.record(RbacChange::AddPermission {
+                    role: role.clone(),
+                    permission,
+                });
+            }
+        }
+    }
+
+    /// Returns the audit log entries of changes to the roles of the user, oldest first.
+    ///
+    /// The result is empty if the audit log hasn't been enabled with `enable_audit`.
+    pub fn audit_for_user(&self, user: &U) -> Vec<AuditEntry<U::Id, R::Id, P::Id>> {
+        let user = user.get_rbac_id();
+        match &self.audit_log {
+            Some(log) => log
+                .entries
+                .iter()
+                .filter(|e| e.action.user() == Some(&user))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Creates an immutable, shareable snapshot of the current state.
+    ///
+    /// Later changes to `self` don't affect the returned snapshot.
+    pub fn arc_snapshot(&self) -> Arc<FrozenRbac<U, R, P, S>> {
+        Arc::new(FrozenRbac {
+            model: self.clone(),
+        })
+    }
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable, S: Clone> Clone for InMemoryRbac<U, R, P, S>
 where
-    U::Id: Eq + Hash,
-    R::Id: Eq + Hash,
-    P::Id: Eq + Hash,
+    U::Id: Eq + Hash + Clone,
+    R::Id: Eq + Hash + Clone,
+    P::Id: Eq + Hash + Clone,
 {
-    user_role_map: HashMap<U::Id, HashSet<R::Id>>,
-    role_permisson_map: HashMap<R::Id, HashSet<P::Id>>,
+    fn clone(&self) -> Self {
+        InMemoryRbac {
+            user_role_map: self.user_role_map.clone(),
+            role_permisson_map: self.role_permisson_map.clone(),
+            max_permissions_per_role: self.max_permissions_per_role,
+            assigned_by_map: self.assigned_by_map.clone(),
+            idempotency_keys: self.idempotency_keys.clone(),
+            prerequisite_map: self.prerequisite_map.clone(),
+            min_roles_map: self.min_roles_map.clone(),
+            audit_log: self.audit_log.clone(),
+            emergency_overrides: self.emergency_overrides.clone(),
+            permission_meta: self.permission_meta.clone(),
+            exclusive_roles_map: self.exclusive_roles_map.clone(),
+            max_users_per_role_map: self.max_users_per_role_map.clone(),
+            strict_roles: self.strict_roles,
+            role_prerequisite_map: self.role_prerequisite_map.clone(),
+            role_parent_map: self.role_parent_map.clone(),
+            role_expiry_map: self.role_expiry_map.clone(),
+            user_denied_map: self.user_denied_map.clone(),
+            permission_group_map: self.permission_group_map.clone(),
+            max_resolution_depth: self.max_resolution_depth,
+            materialized_map: self.materialized_map.clone(),
+            clock: self.clock,
+        }
+    }
 }
 
-impl<U: Identifiable, R: Identifiable, P: Identifiable> InMemoryRbac<U, R, P>
+impl<U: Identifiable, R: Identifiable, P: Identifiable, S> fmt::Debug for InMemoryRbac<U, R, P, S>
+where
+    U::Id: Eq + Hash + fmt::Debug,
+    R::Id: Eq + Hash + fmt::Debug,
+    P::Id: Eq + Hash + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InMemoryRbac")
+            .field("user_role_map", &self.user_role_map)
+            .field("role_permisson_map", &self.role_permisson_map)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Compares everything that is part of the `fingerprint`: role assignments and their expiry
+/// times, permissions, role parents, permission groups, denied permissions, prerequisites and
+/// minimum role counts.
+impl<U: Identifiable, R: Identifiable, P: Identifiable, S: BuildHasher> PartialEq
+    for InMemoryRbac<U, R, P, S>
 where
     U::Id: Eq + Hash,
     R::Id: Eq + Hash,
     P::Id: Eq + Hash,
 {
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
-        InMemoryRbac {
-            user_role_map: HashMap::new(),
-            role_permisson_map: HashMap::new(),
-        }
+    fn eq(&self, other: &Self) -> bool {
+        self.user_role_map == other.user_role_map
+            && self.role_permisson_map == other.role_permisson_map
+            && self.role_parent_map == other.role_parent_map
+            && self.permission_group_map == other.permission_group_map
+            && self.prerequisite_map == other.prerequisite_map
+            && self.min_roles_map == other.min_roles_map
+            && self.user_denied_map == other.user_denied_map
+            && self.role_expiry_map == other.role_expiry_map
     }
 }
 
-impl<'a, U, R, P> RbacIterators<U, R, P> for &'a InMemoryRbac<U, R, P>
+impl<'a, U, R, P, S: BuildHasher> RbacIterators<U, R, P> for &'a InMemoryRbac<U, R, P, S>
 where
     U: Identifiable,
     U::Id: Eq + Hash,
@@ -43,84 +2929,129 @@ where
     P: Identifiable,
     P::Id: Eq + Hash + Clone,
 {
-    type UserRoles = std::iter::Cloned<std::collections::hash_set::Iter<'a, R::Id>>;
-    type RolePermissions = std::iter::Cloned<std::collections::hash_set::Iter<'a, P::Id>>;
+    type UserRoles = UserRoleIds<'a, R::Id>;
+    type RolePermissions = RolePermissionIds<'a, P::Id>;
     type Error = InMemoryRbacError;
 
     fn iter_user_role_ids(self, user: &U) -> Result<Self::UserRoles, Self::Error> {
-        match self.user_role_map.get(&user.get_rbac_id()) {
-            Some(val) => Ok(val.iter().cloned()),
-            None => Err(InMemoryRbacError::UserHasNoRoles),
+        let user = user.get_rbac_id();
+        let roles = match self.user_role_map.get(&user) {
+            Some(roles) => roles,
+            None => return Err(InMemoryRbacError::UserHasNoRoles),
+        };
+        let roles = UserRoleIds {
+            roles: roles.iter(),
+            expiries: self.role_expiry_map.get(&user),
+            now: (self.clock)(),
+        };
+        if roles.clone().next().is_none() {
+            return Err(InMemoryRbacError::UserHasNoRoles);
         }
+        Ok(roles)
     }
 
     fn iter_role_permission_ids(self, role: &R) -> Result<Self::RolePermissions, Self::Error> {
-        match self.role_permisson_map.get(&role.get_rbac_id()) {
-            Some(val) => Ok(val.iter().cloned()),
-            None => Err(InMemoryRbacError::RoleHasNoPermissions),
+        let role = role.get_rbac_id();
+        let direct = match &self.materialized_map {
+            Some(materialized) => materialized.get(&role),
+            None if self.permission_group_map.is_empty()
+                && !self.role_parent_map.contains_key(&role) =>
+            {
+                self.role_permisson_map.get(&role)
+            }
+            None => {
+                let permissions = self.role_permission_set(&role);
+                if permissions.is_empty() {
+                    return Err(InMemoryRbacError::RoleHasNoPermissions);
+                }
+                return Ok(RolePermissionIds {
+                    inner: RolePermissionsInner::Resolved(permissions.into_iter()),
+                });
+            }
+        };
+        match direct {
+            Some(permissions) if !permissions.is_empty() => Ok(RolePermissionIds {
+                inner: RolePermissionsInner::Direct(permissions.iter()),
+            }),
+            _ => Err(InMemoryRbacError::RoleHasNoPermissions),
         }
     }
 }
 
-impl<U: Identifiable, R: Identifiable, P: Identifiable> RbacModel<U, R, P> for InMemoryRbac<U, R, P>
+impl<U: Identifiable, R: Identifiable, P: Identifiable, S: BuildHasher + Clone> RbacModel<U, R, P>
+    for InMemoryRbac<U, R, P, S>
 where
-    U::Id: Eq + Hash,
+    U::Id: Eq + Hash + Clone,
     R::Id: Eq + Hash + Clone,
     P::Id: Eq + Hash + Clone,
 {
     type Error = InMemoryRbacError;
 
     fn assign_role(&mut self, user: &U, role: &R) -> Result<bool, Self::Error> {
-        let entry = self
-            .user_role_map
-            .entry(user.get_rbac_id())
-            .or_insert_with(HashSet::new);
-
-        Ok(entry.insert(role.get_rbac_id()))
+        self.assign_role_id(user.get_rbac_id(), role.get_rbac_id())
     }
 
     fn unassign_role(&mut self, user: &U, role: &R) -> Result<bool, Self::Error> {
-        match self.user_role_map.entry(user.get_rbac_id()) {
-            Entry::Occupied(mut val) => {
-                let was_present = val.get_mut().remove(&role.get_rbac_id());
-                if val.get().is_empty() {
-                    val.remove_entry();
-                }
-                Ok(was_present)
-            }
-            Entry::Vacant(_) => Ok(false),
-        }
+        self.unassign_role_id(&user.get_rbac_id(), &role.get_rbac_id())
     }
 
     fn add_permission(&mut self, role: &R, permission: &P) -> Result<bool, Self::Error> {
-        let entry = self
-            .role_permisson_map
-            .entry(role.get_rbac_id())
-            .or_insert_with(HashSet::new);
-        Ok(entry.insert(permission.get_rbac_id()))
+        self.add_permission_id(role.get_rbac_id(), permission.get_rbac_id())
     }
 
     fn remove_permission(&mut self, role: &R, permission: &P) -> Result<bool, Self::Error> {
-        match self.role_permisson_map.entry(role.get_rbac_id()) {
-            Entry::Occupied(mut val) => {
-                let was_present = val.get_mut().remove(&permission.get_rbac_id());
-                if val.get().is_empty() {
-                    val.remove_entry();
-                }
-                Ok(was_present)
-            }
-            Entry::Vacant(_) => Ok(false),
-        }
+        self.remove_permission_id(&role.get_rbac_id(), &permission.get_rbac_id())
+    }
+
+    fn role_has_permission(&self, role: &R, permission: &P) -> Result<bool, Self::Error> {
+        Ok(self.role_has_permission_id(&role.get_rbac_id(), &permission.get_rbac_id()))
     }
 
     fn user_has_permission(&self, user: &U, permission: &P) -> Result<bool, Self::Error> {
-        match self.user_role_map.get(&user.get_rbac_id()) {
-            Some(val) => Ok(val.iter().any(|r| match self.role_permisson_map.get(r) {
-                Some(val) => val.contains(&permission.get_rbac_id()),
-                None => false,
-            })),
-            None => Ok(false),
-        }
+        Ok(self.any_role_grants(user, permission))
+    }
+}
+
+/// Completes every future immediately, since the model is in memory.
+#[cfg(feature = "async")]
+impl<U, R, P, S> traits::AsyncRbacModel<U, R, P> for InMemoryRbac<U, R, P, S>
+where
+    U: Identifiable + Sync,
+    U::Id: Eq + Hash + Clone + Send + Sync,
+    R: Identifiable + Sync,
+    R::Id: Eq + Hash + Clone + Send + Sync,
+    P: Identifiable + Sync,
+    P::Id: Eq + Hash + Clone + Send + Sync,
+    S: BuildHasher + Clone + Send + Sync,
+{
+    type Error = InMemoryRbacError;
+
+    async fn assign_role(&mut self, user: &U, role: &R) -> Result<bool, Self::Error> {
+        <Self as RbacModel<U, R, P>>::assign_role(self, user, role)
+    }
+
+    async fn unassign_role(&mut self, user: &U, role: &R) -> Result<bool, Self::Error> {
+        <Self as RbacModel<U, R, P>>::unassign_role(self, user, role)
+    }
+
+    async fn add_permission(&mut self, role: &R, permission: &P) -> Result<bool, Self::Error> {
+        <Self as RbacModel<U, R, P>>::add_permission(self, role, permission)
+    }
+
+    async fn remove_permission(&mut self, role: &R, permission: &P) -> Result<bool, Self::Error> {
+        <Self as RbacModel<U, R, P>>::remove_permission(self, role, permission)
+    }
+
+    async fn user_has_role(&self, user: &U, role: &R) -> Result<bool, Self::Error> {
+        <Self as RbacModel<U, R, P>>::user_has_role(self, user, role)
+    }
+
+    async fn role_has_permission(&self, role: &R, permission: &P) -> Result<bool, Self::Error> {
+        <Self as RbacModel<U, R, P>>::role_has_permission(self, role, permission)
+    }
+
+    async fn user_has_permission(&self, user: &U, permission: &P) -> Result<bool, Self::Error> {
+        <Self as RbacModel<U, R, P>>::user_has_permission(self, user, permission)
     }
 }
 
@@ -131,4 +3062,258 @@ pub enum InMemoryRbacError {
     UserHasNoRoles,
     /// A role has no permissions, not even one.
     RoleHasNoPermissions,
+    /// A role already holds the maximum number of permissions.
+    PermissionLimitExceeded,
+    /// A permission would directly or indirectly be its own prerequisite.
+    PrerequisiteCycle,
+    /// A role would directly or indirectly be its own parent.
+    RoleHierarchyCycle,
+    /// A permission group would directly or indirectly contain itself.
+    PermissionGroupCycle,
+    /// A role hierarchy or permission group would be deeper than the configured maximum.
+    ResolutionDepthExceeded,
+    /// Assigning a role would violate a constraint.
+    ConstraintViolated(ConstraintViolation),
+    /// A role can't be assigned because the user lacks one of its prerequisite roles.
+    MissingPrerequisiteRole,
+}
+
+impl NotFound for InMemoryRbacError {
+    fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            InMemoryRbacError::UserHasNoRoles | InMemoryRbacError::RoleHasNoPermissions
+        )
+    }
+}
+
+impl From<ConstraintViolation> for InMemoryRbacError {
+    fn from(violation: ConstraintViolation) -> Self {
+        match violation {
+            ConstraintViolation::MissingPrerequisiteRole => {
+                InMemoryRbacError::MissingPrerequisiteRole
+            }
+            violation => InMemoryRbacError::ConstraintViolated(violation),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A constraint on role assignments, as returned by `InMemoryRbac::can_assign`.
+pub enum ConstraintViolation {
+    /// The user holds a role that is mutually exclusive with the role.
+    MutuallyExclusiveRole,
+    /// The role is already held by the maximum number of users.
+    MaxUsersPerRoleExceeded,
+    /// Roles must be known, but the role has no permissions.
+    UnknownRole,
+    /// The user lacks a prerequisite role of the role.
+    MissingPrerequisiteRole,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The error returned by `InMemoryRbac::ensure_permission` if a user lacks a permission.
+pub struct AccessDenied<UId, PId> {
+    /// The id of the user.
+    pub user: UId,
+    /// The id of the permission the user doesn't have.
+    pub permission: PId,
+}
+
+impl<UId: fmt::Debug, PId: fmt::Debug> fmt::Display for AccessDenied<UId, PId> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "user {:?} doesn't have permission {:?}",
+            self.user, self.permission
+        )
+    }
+}
+
+impl<UId: fmt::Debug, PId: fmt::Debug> std::error::Error for AccessDenied<UId, PId> {}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+/// The number of role assignments changed by `InMemoryRbac::sync_from_map`.
+pub struct SyncReport {
+    /// Assignments that were missing and have been added.
+    pub added: usize,
+    /// Assignments that weren't desired and have been removed.
+    pub removed: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Descriptive metadata about a permission, e.g. to show next to a grant toggle in a UI.
+pub struct PermissionMeta {
+    /// A human readable description of what the permission allows.
+    pub description: String,
+    /// How dangerous it is to grant the permission.
+    pub risk_level: RiskLevel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// How dangerous it is to grant a permission.
+pub enum RiskLevel {
+    /// Granting the permission is harmless.
+    Low,
+    /// Granting the permission should be considered carefully.
+    Medium,
+    /// Granting the permission allows serious damage.
+    High,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The roles and permissions of a user, as returned by `InMemoryRbac::capabilities_response`.
+///
+/// The order of `roles` and `permissions` is unspecified.
+pub struct CapabilitiesResponse<UId, RId, PId> {
+    /// The id of the user.
+    pub user_id: UId,
+    /// The ids of all roles of the user.
+    pub roles: Vec<RId>,
+    /// The ids of all permissions the user has through any of their roles, without duplicates.
+    pub permissions: Vec<PId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The role definitions of a model, created by `InMemoryRbac::export_catalog`.
+///
+/// Unlike role assignments, role definitions rarely change, so they can be kept under version
+/// control and loaded with `InMemoryRbac::import_catalog`.
+pub struct CatalogExport<RId: Eq + Hash, PId: Eq + Hash> {
+    /// The ids of the permissions of each role.
+    pub roles: HashMap<RId, HashSet<PId>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The role assignments and permissions of a model at one point in time, created by
+/// `InMemoryRbac::snapshot`.
+pub struct Snapshot<UId: Eq + Hash, RId: Eq + Hash, PId: Eq + Hash> {
+    assignments: HashMap<UId, HashSet<RId>>,
+    grants: HashMap<RId, HashSet<PId>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The changes between a `Snapshot` and the current state of a model, created by
+/// `InMemoryRbac::diff`.
+pub struct RbacDiff<UId, RId, PId> {
+    /// Pairs of users and the roles that have been assigned to them.
+    pub assigned: Vec<(UId, RId)>,
+    /// Pairs of users and the roles that have been unassigned from them.
+    pub unassigned: Vec<(UId, RId)>,
+    /// Pairs of roles and the permissions that have been added to them.
+    pub added_permissions: Vec<(RId, PId)>,
+    /// Pairs of roles and the permissions that have been removed from them.
+    pub removed_permissions: Vec<(RId, PId)>,
+}
+
+impl<UId, RId, PId> RbacDiff<UId, RId, PId> {
+    /// Returns `true` if nothing has changed.
+    pub fn is_empty(&self) -> bool {
+        self.assigned.is_empty()
+            && self.unassigned.is_empty()
+            && self.added_permissions.is_empty()
+            && self.removed_permissions.is_empty()
+    }
+
+    /// Converts the diff into the changes that turn the earlier state into the current one, e.g.
+    /// to emit them as audit events or to apply them to a replica with
+    /// `InMemoryRbac::apply_change`.
+    pub fn into_changes(self) -> Vec<RbacChange<UId, RId, PId>> {
+        let unassigned = self
+            .unassigned
+            .into_iter()
+            .map(|(user, role)| RbacChange::UnassignRole { user, role });
+        let assigned = self
+            .assigned
+            .into_iter()
+            .map(|(user, role)| RbacChange::AssignRole { user, role });
+        let removed = self
+            .removed_permissions
+            .into_iter()
+            .map(|(role, permission)| RbacChange::RemovePermission { role, permission });
+        let added = self
+            .added_permissions
+            .into_iter()
+            .map(|(role, permission)| RbacChange::AddPermission { role, permission });
+        unassigned
+            .chain(assigned)
+            .chain(removed)
+            .chain(added)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+/// The origin of a permission a user has, as returned by `InMemoryRbac::permission_source`.
+pub enum PermissionSource<RId, PId> {
+    /// The permission is attached to the role of the user with this id.
+    Role(RId),
+    /// The permission is inherited by the role of the user from the last role of `chain`, which
+    /// lists the parents from the direct parent of `role` upwards.
+    Inherited { role: RId, chain: Vec<RId> },
+    /// The permission is a member of `group`, which is attached to `role` or, if `chain` isn't
+    /// empty, to the last of its parents in `chain`.
+    Group {
+        role: RId,
+        chain: Vec<RId>,
+        group: PId,
+    },
+    /// The permission is granted by an emergency override that expires at this time.
+    EmergencyOverride { until: SystemTime },
+}
+
+/// The default number of idempotency keys remembered by `assign_role_idempotent`.
+pub const DEFAULT_IDEMPOTENCY_KEY_CAPACITY: usize = 1024;
+
+/// The default maximum depth of role hierarchies and permission groups, see
+/// `InMemoryRbac::set_max_resolution_depth`.
+pub const DEFAULT_MAX_RESOLUTION_DEPTH: usize = 32;
+
+/// The results of recent idempotent operations, forgetting the oldest key once full.
+#[derive(Clone)]
+struct IdempotencyKeys {
+    results: HashMap<String, bool>,
+    order: VecDeque<String>,
+    capacity: usize,
+}
+
+impl IdempotencyKeys {
+    fn new() -> Self {
+        IdempotencyKeys {
+            results: HashMap::new(),
+            order: VecDeque::new(),
+            capacity: DEFAULT_IDEMPOTENCY_KEY_CAPACITY,
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<bool> {
+        self.results.get(key).copied()
+    }
+
+    fn insert(&mut self, key: &str, result: bool) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.results.remove(&oldest);
+            }
+        }
+        self.order.push_back(key.to_owned());
+        self.results.insert(key.to_owned(), result);
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.order.len() > capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.results.remove(&oldest);
+            }
+        }
+    }
 }
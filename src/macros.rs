@@ -0,0 +1,84 @@
+/// Checks several permissions of a user at once and collects the results in a struct with one
+/// `bool` field per permission.
+///
+/// The first two arguments are a reference to an `RbacModel` and the user. They are followed by
+/// a list of `field: permission` pairs in braces. The macro evaluates to a `Result` holding the
+/// struct, or the first error returned by `RbacModel::user_has_permission`.
+///
+/// # Examples
+/// ```
+/// use rbac::{match_permissions, InMemoryRbac};
+/// use rbac::traits::{Identifiable, RbacModel};
+///
+/// struct User(u32);
+/// struct Role(u32);
+///
+/// enum Permission {
+///     MakeCalls,
+///     AlterState,
+/// }
+///
+/// # impl Identifiable for User {
+/// #     type Id = u32;
+/// #
+/// #     fn get_rbac_id(&self) -> u32 {
+/// #         self.0
+/// #     }
+/// # }
+/// #
+/// # impl Identifiable for Role {
+/// #     type Id = u32;
+/// #
+/// #     fn get_rbac_id(&self) -> u32 {
+/// #         self.0
+/// #     }
+/// # }
+/// #
+/// impl Identifiable for Permission {
+///     type Id = u8;
+///
+///     fn get_rbac_id(&self) -> u8 {
+///         match self {
+///             Permission::MakeCalls => 0,
+///             Permission::AlterState => 1,
+///         }
+///     }
+/// }
+///
+/// let mut memory = InMemoryRbac::new();
+/// memory.assign_role(&User(1), &Role(1)).unwrap();
+/// memory.add_permission(&Role(1), &Permission::MakeCalls).unwrap();
+///
+/// let caps = match_permissions!(&memory, &User(1), {
+///     can_make_calls: Permission::MakeCalls,
+///     can_alter_state: Permission::AlterState,
+/// })
+/// .unwrap();
+///
+/// assert!(caps.can_make_calls);
+/// assert!(!caps.can_alter_state);
+/// ```
+#[macro_export]
+macro_rules! match_permissions {
+    ($model:expr, $user:expr, { $($field:ident : $permission:expr),+ $(,)? }) => {{
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        struct Permissions {
+            $($field: bool,)+
+        }
+
+        let model = $model;
+        let user = $user;
+        (|| {
+            Ok(Permissions {
+                $($field: match $crate::traits::RbacModel::user_has_permission(
+                    model,
+                    user,
+                    &$permission,
+                ) {
+                    Ok(val) => val,
+                    Err(e) => return Err(e),
+                },)+
+            })
+        })()
+    }};
+}
@@ -0,0 +1,140 @@
+use std::hash::Hash;
+
+use crate::traits::{Identifiable, RbacIterators, RbacModel};
+use crate::{InMemoryRbac, InMemoryRbacError};
+
+/// Callbacks for changes to a model wrapped in an `ObservedRbac`, e.g. to invalidate a cache.
+///
+/// Every callback does nothing by default, so only the interesting ones need to be implemented.
+pub trait RbacObserver<UId, RId, PId> {
+    /// Called after a role has been assigned to a user that didn't have it.
+    fn on_assign_role(&self, _user: &UId, _role: &RId) {}
+    /// Called after a role has been unassigned from a user that had it.
+    fn on_unassign_role(&self, _user: &UId, _role: &RId) {}
+    /// Called after a permission has been added to a role that didn't have it.
+    fn on_add_permission(&self, _role: &RId, _permission: &PId) {}
+    /// Called after a permission has been removed from a role that had it.
+    fn on_remove_permission(&self, _role: &RId, _permission: &PId) {}
+}
+
+/// A model that notifies an `RbacObserver` of every change made through `RbacModel`.
+///
+/// Changes that have no effect, like assigning a role the user already has, aren't reported.
+pub struct ObservedRbac<U: Identifiable, R: Identifiable, P: Identifiable, O>
+where
+    U::Id: Eq + Hash,
+    R::Id: Eq + Hash,
+    P::Id: Eq + Hash,
+{
+    model: InMemoryRbac<U, R, P>,
+    observer: O,
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable, O> ObservedRbac<U, R, P, O>
+where
+    U::Id: Eq + Hash,
+    R::Id: Eq + Hash,
+    P::Id: Eq + Hash,
+    O: RbacObserver<U::Id, R::Id, P::Id>,
+{
+    /// Wraps `model`, reporting all further changes to `observer`.
+    pub fn new(model: InMemoryRbac<U, R, P>, observer: O) -> Self {
+        ObservedRbac { model, observer }
+    }
+
+    /// Returns the wrapped model, e.g. to call methods that aren't part of `RbacModel`.
+    pub fn model(&self) -> &InMemoryRbac<U, R, P> {
+        &self.model
+    }
+
+    /// Returns the observer.
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Unwraps the model and the observer.
+    pub fn into_inner(self) -> (InMemoryRbac<U, R, P>, O) {
+        (self.model, self.observer)
+    }
+}
+
+impl<'a, U, R, P, O> RbacIterators<U, R, P> for &'a ObservedRbac<U, R, P, O>
+where
+    U: Identifiable,
+    U::Id: Eq + Hash,
+    R: Identifiable,
+    R::Id: Eq + Hash + Clone,
+    P: Identifiable,
+    P::Id: Eq + Hash + Clone,
+{
+    type UserRoles = <&'a InMemoryRbac<U, R, P> as RbacIterators<U, R, P>>::UserRoles;
+    type RolePermissions = <&'a InMemoryRbac<U, R, P> as RbacIterators<U, R, P>>::RolePermissions;
+    type Error = InMemoryRbacError;
+
+    fn iter_user_role_ids(self, user: &U) -> Result<Self::UserRoles, Self::Error> {
+        self.model.iter_user_role_ids(user)
+    }
+
+    fn iter_role_permission_ids(self, role: &R) -> Result<Self::RolePermissions, Self::Error> {
+        self.model.iter_role_permission_ids(role)
+    }
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable, O> RbacModel<U, R, P>
+    for ObservedRbac<U, R, P, O>
+where
+    U::Id: Eq + Hash + Clone,
+    R::Id: Eq + Hash + Clone,
+    P::Id: Eq + Hash + Clone,
+    O: RbacObserver<U::Id, R::Id, P::Id>,
+{
+    type Error = InMemoryRbacError;
+
+    fn assign_role(&mut self, user: &U, role: &R) -> Result<bool, Self::Error> {
+        let assigned = self.model.assign_role(user, role)?;
+        if assigned {
+            self.observer
+                .on_assign_role(&user.get_rbac_id(), &role.get_rbac_id());
+        }
+        Ok(assigned)
+    }
+
+    fn unassign_role(&mut self, user: &U, role: &R) -> Result<bool, Self::Error> {
+        let unassigned = self.model.unassign_role(user, role)?;
+        if unassigned {
+            self.observer
+                .on_unassign_role(&user.get_rbac_id(), &role.get_rbac_id());
+        }
+        Ok(unassigned)
+    }
+
+    fn add_permission(&mut self, role: &R, permission: &P) -> Result<bool, Self::Error> {
+        let added = self.model.add_permission(role, permission)?;
+        if added {
+            self.observer
+                .on_add_permission(&role.get_rbac_id(), &permission.get_rbac_id());
+        }
+        Ok(added)
+    }
+
+    fn remove_permission(&mut self, role: &R, permission: &P) -> Result<bool, Self::Error> {
+        let removed = self.model.remove_permission(role, permission)?;
+        if removed {
+            self.observer
+                .on_remove_permission(&role.get_rbac_id(), &permission.get_rbac_id());
+        }
+        Ok(removed)
+    }
+
+    fn user_has_role(&self, user: &U, role: &R) -> Result<bool, Self::Error> {
+        self.model.user_has_role(user, role)
+    }
+
+    fn role_has_permission(&self, role: &R, permission: &P) -> Result<bool, Self::Error> {
+        self.model.role_has_permission(role, permission)
+    }
+
+    fn user_has_permission(&self, user: &U, permission: &P) -> Result<bool, Self::Error> {
+        RbacModel::user_has_permission(&self.model, user, permission)
+    }
+}
@@ -0,0 +1,70 @@
+use std::fmt::Display;
+use std::hash::{BuildHasher, Hash};
+
+use crate::traits::Identifiable;
+use crate::InMemoryRbac;
+
+/// A relationship tuple of OpenFGA, created by `InMemoryRbac::to_openfga_tuples`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FgaTuple {
+    /// The subject of the relationship, e.g. `user:12` or `role:110#assignee`.
+    pub user: String,
+    /// The name of the relation, e.g. `assignee`.
+    pub relation: String,
+    /// The object of the relationship, e.g. `role:110`.
+    pub object: String,
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable, S: BuildHasher> InMemoryRbac<U, R, P, S>
+where
+    U::Id: Eq + Hash + Display,
+    R::Id: Eq + Hash + Display,
+    P::Id: Eq + Hash + Display,
+{
+    /// Exports all role assignments and permission grants as OpenFGA relationship tuples.
+    ///
+    /// The tuples fit an authorization model with the types `user`, `role` and `permission`:
+    ///
+    /// ```text
+    /// type user
+    /// type role
+    ///   relations
+    ///     define assignee: [user, role#assignee]
+    /// type permission
+    ///   relations
+    ///     define granted: [role#assignee]
+    /// ```
+    ///
+    /// A role assignment becomes `(user:<user>, assignee, role:<role>)`, a permission grant
+    /// becomes `(role:<role>#assignee, granted, permission:<permission>)` and a role parent
+    /// becomes `(role:<role>#assignee, assignee, role:<parent>)`. Expired role assignments are
+    /// left out.
+    pub fn to_openfga_tuples(&self) -> Vec<FgaTuple> {
+        let assignments = self.user_role_map.keys().flat_map(|user| {
+            self.active_roles(user).map(move |role| FgaTuple {
+                user: format!("user:{}", user),
+                relation: "assignee".to_owned(),
+                object: format!("role:{}", role),
+            })
+        });
+        let grants = self
+            .role_permisson_map
+            .iter()
+            .flat_map(|(role, permissions)| {
+                permissions.iter().map(move |permission| FgaTuple {
+                    user: format!("role:{}#assignee", role),
+                    relation: "granted".to_owned(),
+                    object: format!("permission:{}", permission),
+                })
+            });
+        let parents = self.role_parent_map.iter().flat_map(|(role, parents)| {
+            parents.iter().map(move |parent| FgaTuple {
+                user: format!("role:{}#assignee", role),
+                relation: "assignee".to_owned(),
+                object: format!("role:{}", parent),
+            })
+        });
+        assignments.chain(grants).chain(parents).collect()
+    }
+}
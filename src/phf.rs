@@ -0,0 +1,160 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hash};
+use std::time::SystemTime;
+
+use boomphf::Mphf;
+
+use crate::traits::Identifiable;
+use crate::InMemoryRbac;
+
+/// A read-only model indexed by a minimal perfect hash over the user ids, created by
+/// `InMemoryRbac::into_phf`.
+///
+/// The effective permissions of every user are computed up front, once for every point in time at
+/// which one of the user's roles expires, so a check is a single hash of the user id followed by
+/// a set lookup. A `PhfRbac` can't be changed; build a new one instead.
+pub struct PhfRbac<U: Identifiable, R: Identifiable, P: Identifiable>
+where
+    U::Id: Hash + Debug,
+{
+    mphf: Mphf<U::Id>,
+    entries: Vec<PhfEntry<U::Id, R::Id, P::Id>>,
+    clock: fn() -> SystemTime,
+}
+
+struct PhfEntry<UId, RId, PId> {
+    user: UId,
+    roles: HashMap<RId, Option<SystemTime>>,
+    /// The permissions of the user until the time of each stage, ordered by time. The last stage
+    /// has no time.
+    stages: Vec<(Option<SystemTime>, HashSet<PId>)>,
+    emergency_override: Option<SystemTime>,
+}
+
+fn not_expired(until: &Option<SystemTime>, now: SystemTime) -> bool {
+    until.is_none_or(|until| now < until)
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable> PhfRbac<U, R, P>
+where
+    U::Id: Eq + Hash + Debug,
+    R::Id: Eq + Hash,
+    P::Id: Eq + Hash,
+{
+    fn entry(&self, user: &U::Id) -> Option<&PhfEntry<U::Id, R::Id, P::Id>> {
+        let index = self.mphf.try_hash(user)?;
+        self.entries
+            .get(index as usize)
+            .filter(|entry| entry.user == *user)
+    }
+
+    /// Checks whether the user has the role.
+    pub fn user_has_role(&self, user: &U, role: &R) -> bool {
+        match self.entry(&user.get_rbac_id()) {
+            Some(entry) => entry
+                .roles
+                .get(&role.get_rbac_id())
+                .is_some_and(|until| not_expired(until, (self.clock)())),
+            None => false,
+        }
+    }
+
+    /// Checks whether the user has the permission.
+    pub fn user_has_permission(&self, user: &U, permission: &P) -> bool {
+        match self.entry(&user.get_rbac_id()) {
+            Some(entry) => {
+                let now = (self.clock)();
+                entry
+                    .stages
+                    .iter()
+                    .find(|(until, _)| not_expired(until, now))
+                    .is_some_and(|(_, permissions)| permissions.contains(&permission.get_rbac_id()))
+                    || entry.emergency_override.is_some_and(|until| now < until)
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the number of users in the model.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the model has no users.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable, S: BuildHasher + Clone>
+    InMemoryRbac<U, R, P, S>
+where
+    U::Id: Eq + Hash + Clone + Debug,
+    R::Id: Eq + Hash + Clone,
+    P::Id: Eq + Hash + Clone,
+{
+    /// Converts the model into a `PhfRbac` for the fastest possible checks.
+    ///
+    /// Building the perfect hash takes time linear in the number of users, so this is meant for
+    /// models that are loaded once and never changed. Role assignments that have expired by then
+    /// are left out, the others stop granting their permissions once they expire. The model keeps
+    /// the clock set with `set_clock`.
+    pub fn into_phf(self) -> PhfRbac<U, R, P> {
+        let mut users: Vec<U::Id> = self.user_role_map.keys().cloned().collect();
+        users.extend(
+            self.emergency_overrides
+                .keys()
+                .filter(|user| !self.user_role_map.contains_key(user))
+                .cloned(),
+        );
+        let mphf = Mphf::new(1.7, &users);
+        let mut entries: Vec<_> = users
+            .into_iter()
+            .map(|user| {
+                let mut roles: Vec<&R::Id> = self.active_roles(&user).collect();
+                let expiry = |role: &R::Id| {
+                    self.role_expiry_map
+                        .get(&user)
+                        .and_then(|expiries| expiries.get(role))
+                        .copied()
+                };
+                let roles_with_expiry = roles
+                    .iter()
+                    .map(|&role| (role.clone(), expiry(role)))
+                    .collect();
+                let mut expiries: Vec<(SystemTime, &R::Id)> = roles
+                    .iter()
+                    .filter_map(|&role| expiry(role).map(|until| (until, role)))
+                    .collect();
+                expiries.sort_by_key(|&(until, _)| until);
+                let mut expiries = expiries.into_iter().peekable();
+                let mut stages = Vec::new();
+                loop {
+                    let until = expiries.peek().map(|&(until, _)| until);
+                    let permissions = self.roles_permission_set(&user, &roles);
+                    stages.push((until, permissions.into_iter().cloned().collect()));
+                    let until = match until {
+                        Some(until) => until,
+                        None => break,
+                    };
+                    while let Some((_, expired)) = expiries.next_if(|&(next, _)| next == until) {
+                        roles.retain(|&role| role != expired);
+                    }
+                }
+                PhfEntry {
+                    roles: roles_with_expiry,
+                    stages,
+                    emergency_override: self.emergency_overrides.get(&user).copied(),
+                    user,
+                }
+            })
+            .collect();
+        entries.sort_by_cached_key(|entry| mphf.hash(&entry.user));
+        PhfRbac {
+            mphf,
+            entries,
+            clock: self.clock,
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hash};
+use std::time::SystemTime;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::traits::Identifiable;
+use crate::{InMemoryRbac, DEFAULT_MAX_RESOLUTION_DEPTH};
+
+#[derive(Serialize)]
+#[serde(bound(serialize = "UId: Serialize, RId: Serialize, PId: Serialize"))]
+struct ModelDataRef<'a, UId: Eq + Hash, RId: Eq + Hash, PId: Eq + Hash, H> {
+    assignments: &'a HashMap<UId, HashSet<RId>, H>,
+    grants: &'a HashMap<RId, HashSet<PId>, H>,
+    prerequisites: &'a HashMap<PId, PId>,
+    min_roles: &'a HashMap<PId, usize>,
+    parents: &'a HashMap<RId, HashSet<RId>>,
+    groups: &'a HashMap<PId, HashSet<PId>>,
+    expiries: &'a HashMap<UId, HashMap<RId, SystemTime>>,
+    denied: &'a HashMap<UId, HashSet<PId>>,
+    overrides: &'a HashMap<UId, SystemTime>,
+    max_resolution_depth: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(bound(
+    deserialize = "UId: Deserialize<'de>, RId: Deserialize<'de>, PId: Deserialize<'de>, H: BuildHasher + Default"
+))]
+struct ModelData<UId: Eq + Hash, RId: Eq + Hash, PId: Eq + Hash, H> {
+    assignments: HashMap<UId, HashSet<RId>, H>,
+    grants: HashMap<RId, HashSet<PId>, H>,
+    #[serde(default = "HashMap::new")]
+    prerequisites: HashMap<PId, PId>,
+    #[serde(default = "HashMap::new")]
+    min_roles: HashMap<PId, usize>,
+    #[serde(default = "HashMap::new")]
+    parents: HashMap<RId, HashSet<RId>>,
+    #[serde(default = "HashMap::new")]
+    groups: HashMap<PId, HashSet<PId>>,
+    #[serde(default = "HashMap::new")]
+    expiries: HashMap<UId, HashMap<RId, SystemTime>>,
+    #[serde(default = "HashMap::new")]
+    denied: HashMap<UId, HashSet<PId>>,
+    #[serde(default = "HashMap::new")]
+    overrides: HashMap<UId, SystemTime>,
+    #[serde(default = "default_max_resolution_depth")]
+    max_resolution_depth: usize,
+}
+
+fn default_max_resolution_depth() -> usize {
+    DEFAULT_MAX_RESOLUTION_DEPTH
+}
+
+/// Serializes the role assignments and their expiry times, permissions, denied permissions,
+/// emergency overrides, role parents, permission groups, prerequisites, minimum role counts and
+/// the maximum resolution depth. Other settings like limits and constraints aren't serialized.
+impl<U: Identifiable, R: Identifiable, P: Identifiable, H> Serialize for InMemoryRbac<U, R, P, H>
+where
+    U::Id: Eq + Hash + Serialize,
+    R::Id: Eq + Hash + Serialize,
+    P::Id: Eq + Hash + Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ModelDataRef {
+            assignments: &self.user_role_map,
+            grants: &self.role_permisson_map,
+            prerequisites: &self.prerequisite_map,
+            min_roles: &self.min_roles_map,
+            parents: &self.role_parent_map,
+            groups: &self.permission_group_map,
+            expiries: &self.role_expiry_map,
+            denied: &self.user_denied_map,
+            overrides: &self.emergency_overrides,
+            max_resolution_depth: self.max_resolution_depth,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, U: Identifiable, R: Identifiable, P: Identifiable, H> Deserialize<'de>
+    for InMemoryRbac<U, R, P, H>
+where
+    U::Id: Eq + Hash + Deserialize<'de>,
+    R::Id: Eq + Hash + Clone + Deserialize<'de>,
+    P::Id: Eq + Hash + Deserialize<'de>,
+    H: BuildHasher + Clone + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = ModelData::<U::Id, R::Id, P::Id, H>::deserialize(deserializer)?;
+
+        let mut model = InMemoryRbac::with_hasher(data.assignments.hasher().clone());
+        model.max_resolution_depth = data.max_resolution_depth;
+        model.user_role_map = data.assignments;
+        model.user_role_map.retain(|_, roles| !roles.is_empty());
+        model.role_permisson_map = data.grants;
+        model
+            .role_permisson_map
+            .retain(|_, permissions| !permissions.is_empty());
+        for (permission, prerequisite) in data.prerequisites {
+            model
+                .set_permission_prerequisite_id(permission, prerequisite)
+                .map_err(|_| D::Error::custom("prerequisites contain a cycle"))?;
+        }
+        for (role, parents) in data.parents {
+            for parent in parents {
+                model
+                    .add_role_parent_id(role.clone(), parent)
+                    .map_err(|_| {
+                        D::Error::custom("role parents contain a cycle or are nested too deeply")
+                    })?;
+            }
+        }
+        for (group, members) in data.groups {
+            model
+                .define_permission_group_id(group, members)
+                .map_err(|_| {
+                    D::Error::custom("permission groups contain a cycle or are nested too deeply")
+                })?;
+        }
+        model.role_expiry_map = data.expiries;
+        let assignments = &model.user_role_map;
+        model.role_expiry_map.retain(|user, roles| {
+            roles.retain(|role, _| assignments.get(user).is_some_and(|r| r.contains(role)));
+            !roles.is_empty()
+        });
+        model.user_denied_map = data.denied;
+        model
+            .user_denied_map
+            .retain(|_, permissions| !permissions.is_empty());
+        model.emergency_overrides = data.overrides;
+        model.min_roles_map = data.min_roles;
+        model
+            .min_roles_map
+            .retain(|_, &mut min_roles| min_roles > 1);
+        Ok(model)
+    }
+}
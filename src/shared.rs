@@ -0,0 +1,94 @@
+use std::hash::Hash;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::traits::{Identifiable, RbacModel};
+use crate::{InMemoryRbac, InMemoryRbacError};
+
+/// A model that can be shared between threads and changed by any of them.
+///
+/// Checks take a read lock and changes take a write lock, so checks run concurrently but wait
+/// for changes. Cloning a `SharedRbac` is cheap and yields another handle to the same model.
+pub struct SharedRbac<U: Identifiable, R: Identifiable, P: Identifiable>
+where
+    U::Id: Eq + Hash,
+    R::Id: Eq + Hash,
+    P::Id: Eq + Hash,
+{
+    inner: Arc<RwLock<InMemoryRbac<U, R, P>>>,
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable> Clone for SharedRbac<U, R, P>
+where
+    U::Id: Eq + Hash,
+    R::Id: Eq + Hash,
+    P::Id: Eq + Hash,
+{
+    fn clone(&self) -> Self {
+        SharedRbac {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable> SharedRbac<U, R, P>
+where
+    U::Id: Eq + Hash + Clone,
+    R::Id: Eq + Hash + Clone,
+    P::Id: Eq + Hash + Clone,
+{
+    /// Creates a shared model from `model`.
+    pub fn new(model: InMemoryRbac<U, R, P>) -> Self {
+        SharedRbac {
+            inner: Arc::new(RwLock::new(model)),
+        }
+    }
+
+    /// Locks the model for reading, e.g. to call methods that aren't forwarded.
+    ///
+    /// A model whose lock has been poisoned by a panic is still returned.
+    pub fn read(&self) -> RwLockReadGuard<'_, InMemoryRbac<U, R, P>> {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the model for writing, e.g. to call methods that aren't forwarded.
+    ///
+    /// A model whose lock has been poisoned by a panic is still returned.
+    pub fn write(&self) -> RwLockWriteGuard<'_, InMemoryRbac<U, R, P>> {
+        self.inner.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Checks whether the user has the role, like `RbacModel::user_has_role`.
+    pub fn user_has_role(&self, user: &U, role: &R) -> Result<bool, InMemoryRbacError> {
+        self.read().user_has_role(user, role)
+    }
+
+    /// Checks whether the role has the permission, like `RbacModel::role_has_permission`.
+    pub fn role_has_permission(&self, role: &R, permission: &P) -> Result<bool, InMemoryRbacError> {
+        self.read().role_has_permission(role, permission)
+    }
+
+    /// Checks whether the user has the permission, like `RbacModel::user_has_permission`.
+    pub fn user_has_permission(&self, user: &U, permission: &P) -> Result<bool, InMemoryRbacError> {
+        RbacModel::user_has_permission(&*self.read(), user, permission)
+    }
+
+    /// Assigns a role to a user, like `RbacModel::assign_role`.
+    pub fn assign_role(&self, user: &U, role: &R) -> Result<bool, InMemoryRbacError> {
+        self.write().assign_role(user, role)
+    }
+
+    /// Unassigns a role from a user, like `RbacModel::unassign_role`.
+    pub fn unassign_role(&self, user: &U, role: &R) -> Result<bool, InMemoryRbacError> {
+        self.write().unassign_role(user, role)
+    }
+
+    /// Adds a permission to a role, like `RbacModel::add_permission`.
+    pub fn add_permission(&self, role: &R, permission: &P) -> Result<bool, InMemoryRbacError> {
+        self.write().add_permission(role, permission)
+    }
+
+    /// Removes a permission from a role, like `RbacModel::remove_permission`.
+    pub fn remove_permission(&self, role: &R, permission: &P) -> Result<bool, InMemoryRbacError> {
+        self.write().remove_permission(role, permission)
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::traits::{Identifiable, RbacModel};
+use crate::{InMemoryRbac, InMemoryRbacError};
+
+/// A collection of `InMemoryRbac` models, one per tenant, that are fully isolated from each
+/// other.
+///
+/// The model of a tenant is created empty on the first write to it. Reading from a tenant that
+/// has never been written to behaves like reading from an empty model.
+pub struct MultiTenantRbac<T: Identifiable, U: Identifiable, R: Identifiable, P: Identifiable>
+where
+    T::Id: Eq + Hash,
+    U::Id: Eq + Hash,
+    R::Id: Eq + Hash,
+    P::Id: Eq + Hash,
+{
+    tenants: HashMap<T::Id, InMemoryRbac<U, R, P>>,
+}
+
+impl<T: Identifiable, U: Identifiable, R: Identifiable, P: Identifiable> MultiTenantRbac<T, U, R, P>
+where
+    T::Id: Eq + Hash,
+    U::Id: Eq + Hash + Clone,
+    R::Id: Eq + Hash + Clone,
+    P::Id: Eq + Hash + Clone,
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        MultiTenantRbac {
+            tenants: HashMap::new(),
+        }
+    }
+
+    /// Returns the model of a tenant, if anything has been written to it.
+    pub fn tenant(&self, tenant: &T) -> Option<&InMemoryRbac<U, R, P>> {
+        self.tenants.get(&tenant.get_rbac_id())
+    }
+
+    /// Returns the model of a tenant, creating an empty one if it doesn't exist yet.
+    pub fn tenant_mut(&mut self, tenant: &T) -> &mut InMemoryRbac<U, R, P> {
+        self.tenants
+            .entry(tenant.get_rbac_id())
+            .or_insert_with(InMemoryRbac::new)
+    }
+
+    /// Removes a tenant and returns its model.
+    pub fn remove_tenant(&mut self, tenant: &T) -> Option<InMemoryRbac<U, R, P>> {
+        self.tenants.remove(&tenant.get_rbac_id())
+    }
+
+    /// Assigns a role to a user within a tenant.
+    pub fn assign_role(
+        &mut self,
+        tenant: &T,
+        user: &U,
+        role: &R,
+    ) -> Result<bool, InMemoryRbacError> {
+        self.tenant_mut(tenant).assign_role(user, role)
+    }
+
+    /// Unassigns a role from a user within a tenant.
+    pub fn unassign_role(
+        &mut self,
+        tenant: &T,
+        user: &U,
+        role: &R,
+    ) -> Result<bool, InMemoryRbacError> {
+        match self.tenants.get_mut(&tenant.get_rbac_id()) {
+            Some(model) => model.unassign_role(user, role),
+            None => Ok(false),
+        }
+    }
+
+    /// Adds a permission to a role within a tenant.
+    pub fn add_permission(
+        &mut self,
+        tenant: &T,
+        role: &R,
+        permission: &P,
+    ) -> Result<bool, InMemoryRbacError> {
+        self.tenant_mut(tenant).add_permission(role, permission)
+    }
+
+    /// Removes a permission from a role within a tenant.
+    pub fn remove_permission(
+        &mut self,
+        tenant: &T,
+        role: &R,
+        permission: &P,
+    ) -> Result<bool, InMemoryRbacError> {
+        match self.tenants.get_mut(&tenant.get_rbac_id()) {
+            Some(model) => model.remove_permission(role, permission),
+            None => Ok(false),
+        }
+    }
+
+    /// Checks whether the user has the role within a tenant.
+    pub fn user_has_role(&self, tenant: &T, user: &U, role: &R) -> Result<bool, InMemoryRbacError> {
+        match self.tenant(tenant) {
+            Some(model) => model.user_has_role(user, role),
+            None => Ok(false),
+        }
+    }
+
+    /// Checks whether the user has the permission within a tenant.
+    pub fn user_has_permission(
+        &self,
+        tenant: &T,
+        user: &U,
+        permission: &P,
+    ) -> Result<bool, InMemoryRbacError> {
+        match self.tenant(tenant) {
+            Some(model) => RbacModel::user_has_permission(model, user, permission),
+            None => Ok(false),
+        }
+    }
+}
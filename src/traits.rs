@@ -1,3 +1,10 @@
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::hash::Hash;
+
+use crate::PermExpr;
+
 /// The Identifiable trait needs to be implemented for the types that are used with `RbacModel`
 /// and `RbacIterators`.
 /// # Examples
@@ -46,6 +53,64 @@ pub trait Identifiable {
     fn get_rbac_id(&self) -> Self::Id;
 }
 
+impl Identifiable for String {
+    type Id = String;
+
+    fn get_rbac_id(&self) -> Self::Id {
+        self.clone()
+    }
+}
+
+/// Marker for `AsPermission` implemented by references to permission objects.
+pub enum ByReference {}
+
+/// Marker for `AsPermission` implemented by bare permission ids.
+pub enum ById {}
+
+/// Something that refers to a permission of type `P`, either a `&P` or a bare `P::Id`.
+///
+/// The marker type `M` only exists to keep the two implementations apart and is inferred by the
+/// compiler.
+/// # Examples
+/// ```
+/// # use rbac::traits::{AsPermission, Identifiable};
+/// #
+/// # struct MyPermission {
+/// #     id: u32,
+/// # }
+/// #
+/// # impl Identifiable for MyPermission {
+/// #     type Id = u32;
+/// #
+/// #     fn get_rbac_id(&self) -> Self::Id {
+/// #        self.id
+/// #     }
+/// # }
+/// #
+/// fn id_of<A: AsPermission<MyPermission, M>, M>(permission: A) -> u32 {
+///     permission.into_permission_id()
+/// }
+///
+/// assert_eq!(id_of(&MyPermission { id: 3 }), 3);
+/// assert_eq!(id_of(3), 3);
+/// ```
+pub trait AsPermission<P: Identifiable, M> {
+    /// Gets the `Id` of the permission.
+    fn into_permission_id(self) -> P::Id;
+}
+
+impl<P: Identifiable> AsPermission<P, ByReference> for &P {
+    fn into_permission_id(self) -> P::Id {
+        self.get_rbac_id()
+    }
+}
+
+impl<P: Identifiable> AsPermission<P, ById> for P::Id {
+    fn into_permission_id(self) -> P::Id {
+        self
+    }
+}
+
 /// A trait for providing methods for iterating over roles and permissions.
 /// # Example
 /// Implementing RbacIterators for your type:
@@ -125,6 +190,14 @@ where
     fn iter_role_permission_ids(self, role: &R) -> Result<Self::RolePermissions, Self::Error>;
 }
 
+/// Errors of `RbacIterators` that can tell a missing user or role apart from a real failure,
+/// like a lost connection to a database.
+pub trait NotFound {
+    /// Returns `true` if the error only means that the user has no roles or the role has no
+    /// permissions.
+    fn is_not_found(&self) -> bool;
+}
+
 pub trait RbacModel<U, R, P>
 where
     for<'a> &'a Self: RbacIterators<U, R, P>,
@@ -140,17 +213,176 @@ where
     fn unassign_role(&mut self, user: &U, role: &R) -> Result<bool, Self::Error>;
     fn add_permission(&mut self, role: &R, permission: &P) -> Result<bool, Self::Error>;
     fn remove_permission(&mut self, role: &R, permission: &P) -> Result<bool, Self::Error>;
-    fn user_has_role(&self, user: &U, role: &R) -> Result<bool, Self::Error> {
+    /// Checks whether the user has the role.
+    ///
+    /// A user without any roles doesn't have the role, but any other error of
+    /// `iter_user_role_ids` is returned.
+    fn user_has_role(&self, user: &U, role: &R) -> Result<bool, Self::Error>
+    where
+        for<'a> <&'a Self as RbacIterators<U, R, P>>::Error: NotFound + Into<Self::Error>,
+    {
         match self.iter_user_role_ids(user) {
             Ok(mut val) => Ok(val.any(|r| r == role.get_rbac_id())),
-            Err(_) => Ok(false),
+            Err(err) if err.is_not_found() => Ok(false),
+            Err(err) => Err(err.into()),
         }
     }
-    fn role_has_permission(&self, role: &R, permission: &P) -> Result<bool, Self::Error> {
+    /// Checks whether the role has the permission.
+    ///
+    /// A role without any permissions doesn't have the permission, but any other error of
+    /// `iter_role_permission_ids` is returned.
+    fn role_has_permission(&self, role: &R, permission: &P) -> Result<bool, Self::Error>
+    where
+        for<'a> <&'a Self as RbacIterators<U, R, P>>::Error: NotFound + Into<Self::Error>,
+    {
         match self.iter_role_permission_ids(role) {
             Ok(mut val) => Ok(val.any(|p| p == permission.get_rbac_id())),
-            Err(_) => Ok(false),
+            Err(err) if err.is_not_found() => Ok(false),
+            Err(err) => Err(err.into()),
         }
     }
     fn user_has_permission(&self, user: &U, permission: &P) -> Result<bool, Self::Error>;
+
+    /// Evaluates a permission expression for a user.
+    ///
+    /// `And` and `Or` short-circuit, so not every permission in `expr` is necessarily checked.
+    fn evaluate(&self, user: &U, expr: &PermExpr<P>) -> Result<bool, Self::Error> {
+        match expr {
+            PermExpr::And(exprs) => {
+                for e in exprs {
+                    if !self.evaluate(user, e)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            PermExpr::Or(exprs) => {
+                for e in exprs {
+                    if self.evaluate(user, e)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            PermExpr::Not(e) => Ok(!self.evaluate(user, e)?),
+            PermExpr::Has(permission) => self.user_has_permission(user, permission),
+        }
+    }
+
+    /// Computes the Jaccard index of the role sets of two users, i.e. the number of roles they
+    /// share divided by the number of roles either of them has.
+    ///
+    /// Two users without any roles are considered identical, with a similarity of `1.0`. Any
+    /// other error of `iter_user_role_ids` is returned.
+    fn role_similarity(&self, a: &U, b: &U) -> Result<f64, Self::Error>
+    where
+        R::Id: Hash,
+        for<'a> <&'a Self as RbacIterators<U, R, P>>::Error: NotFound + Into<Self::Error>,
+    {
+        let roles = |user: &U| -> Result<HashSet<R::Id>, Self::Error> {
+            match self.iter_user_role_ids(user) {
+                Ok(val) => Ok(val.collect()),
+                Err(err) if err.is_not_found() => Ok(HashSet::new()),
+                Err(err) => Err(err.into()),
+            }
+        };
+        let a = roles(a)?;
+        let b = roles(b)?;
+        let union = a.union(&b).count();
+        if union == 0 {
+            return Ok(1.0);
+        }
+        Ok(a.intersection(&b).count() as f64 / union as f64)
+    }
+
+    /// Checks for each of the users whether they have the permission.
+    fn which_users_have(
+        &self,
+        users: &[U],
+        permission: &P,
+    ) -> Result<HashMap<U::Id, bool>, Self::Error>
+    where
+        U::Id: Eq + Hash,
+    {
+        let mut result = HashMap::with_capacity(users.len());
+        for user in users {
+            result.insert(
+                user.get_rbac_id(),
+                self.user_has_permission(user, permission)?,
+            );
+        }
+        Ok(result)
+    }
+
+    /// Checks whether both models make the same decision for every pair of the users and
+    /// permissions, no matter how they are structured internally.
+    ///
+    /// The first error of a check is returned.
+    fn access_equivalent(
+        &self,
+        other: &Self,
+        users: &[U],
+        permissions: &[P],
+    ) -> Result<bool, Self::Error> {
+        for user in users {
+            for permission in permissions {
+                if self.user_has_permission(user, permission)?
+                    != other.user_has_permission(user, permission)?
+                {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// An asynchronous counterpart of `RbacModel`, e.g. for models backed by a database that is
+/// accessed through an async connection pool.
+///
+/// The returned futures are `Send`, so they can be spawned on multithreaded executors.
+#[cfg(feature = "async")]
+pub trait AsyncRbacModel<U, R, P>
+where
+    U: Identifiable,
+    R: Identifiable,
+    P: Identifiable,
+{
+    type Error;
+
+    fn assign_role(
+        &mut self,
+        user: &U,
+        role: &R,
+    ) -> impl Future<Output = Result<bool, Self::Error>> + Send;
+    fn unassign_role(
+        &mut self,
+        user: &U,
+        role: &R,
+    ) -> impl Future<Output = Result<bool, Self::Error>> + Send;
+    fn add_permission(
+        &mut self,
+        role: &R,
+        permission: &P,
+    ) -> impl Future<Output = Result<bool, Self::Error>> + Send;
+    fn remove_permission(
+        &mut self,
+        role: &R,
+        permission: &P,
+    ) -> impl Future<Output = Result<bool, Self::Error>> + Send;
+    fn user_has_role(
+        &self,
+        user: &U,
+        role: &R,
+    ) -> impl Future<Output = Result<bool, Self::Error>> + Send;
+    fn role_has_permission(
+        &self,
+        role: &R,
+        permission: &P,
+    ) -> impl Future<Output = Result<bool, Self::Error>> + Send;
+    fn user_has_permission(
+        &self,
+        user: &U,
+        permission: &P,
+    ) -> impl Future<Output = Result<bool, Self::Error>> + Send;
 }
@@ -0,0 +1,107 @@
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::traits::Identifiable;
+use crate::InMemoryRbac;
+
+/// The version of the format written by `InMemoryRbac::to_versioned_json`.
+pub const MODEL_VERSION: u32 = 1;
+
+/// The error returned by `InMemoryRbac::from_versioned_json`.
+#[derive(Debug)]
+pub enum VersionedJsonError {
+    /// The input isn't valid JSON or doesn't match the format of its version.
+    Json(serde_json::Error),
+    /// The input was written in a version this crate can't read.
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for VersionedJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionedJsonError::Json(err) => write!(f, "invalid model: {}", err),
+            VersionedJsonError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported model version {}, expected {}",
+                version, MODEL_VERSION
+            ),
+        }
+    }
+}
+
+impl Error for VersionedJsonError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VersionedJsonError::Json(err) => Some(err),
+            VersionedJsonError::UnsupportedVersion(_) => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for VersionedJsonError {
+    fn from(err: serde_json::Error) -> Self {
+        VersionedJsonError::Json(err)
+    }
+}
+
+#[derive(Serialize)]
+struct Envelope<T> {
+    version: u32,
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct RawEnvelope {
+    version: u32,
+    data: Value,
+}
+
+impl<U: Identifiable, R: Identifiable, P: Identifiable, S: BuildHasher + Clone + Default>
+    InMemoryRbac<U, R, P, S>
+where
+    U::Id: Eq + Hash + Serialize + DeserializeOwned,
+    R::Id: Eq + Hash + Clone + Serialize + DeserializeOwned,
+    P::Id: Eq + Hash + Serialize + DeserializeOwned,
+{
+    /// Serializes the model to JSON wrapped in an envelope of the form
+    /// `{ "version": 1, "data": ... }`, so it can still be read by later versions of this crate.
+    ///
+    /// The data is the model serialized with serde, so settings like limits and constraints
+    /// aren't stored.
+    pub fn to_versioned_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&Envelope {
+            version: MODEL_VERSION,
+            data: self,
+        })
+    }
+
+    /// Reads a model written by `to_versioned_json`.
+    ///
+    /// Fails with `VersionedJsonError::UnsupportedVersion` if the model was written in any other
+    /// version than `MODEL_VERSION`.
+    pub fn from_versioned_json(json: &str) -> Result<Self, VersionedJsonError> {
+        Self::from_versioned_json_with(json, |version, _| {
+            Err(VersionedJsonError::UnsupportedVersion(version))
+        })
+    }
+
+    /// Like `from_versioned_json`, but lets `migrate` convert the data of any other version than
+    /// `MODEL_VERSION` into the data of the current version.
+    pub fn from_versioned_json_with<F>(json: &str, migrate: F) -> Result<Self, VersionedJsonError>
+    where
+        F: FnOnce(u32, Value) -> Result<Value, VersionedJsonError>,
+    {
+        let envelope: RawEnvelope = serde_json::from_str(json)?;
+        let data = if envelope.version == MODEL_VERSION {
+            envelope.data
+        } else {
+            migrate(envelope.version, envelope.data)?
+        };
+        Ok(serde_json::from_value(data)?)
+    }
+}
@@ -0,0 +1,92 @@
+extern crate rbac;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rbac::traits::{Identifiable, RbacModel};
+use rbac::*;
+
+// Counts every allocation made by this test binary, which is why this test lives in its own file.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+struct MyUser {
+    id: u32,
+}
+
+impl Identifiable for MyUser {
+    type Id = u32;
+
+    fn get_rbac_id(&self) -> Self::Id {
+        self.id
+    }
+}
+
+struct MyRole {
+    id: u32,
+}
+
+impl Identifiable for MyRole {
+    type Id = u32;
+
+    fn get_rbac_id(&self) -> Self::Id {
+        self.id
+    }
+}
+
+struct MyPermission {
+    id: u32,
+}
+
+impl Identifiable for MyPermission {
+    type Id = u32;
+
+    fn get_rbac_id(&self) -> Self::Id {
+        self.id
+    }
+}
+
+#[test]
+fn user_has_permission_does_not_allocate() {
+    let mut memory: InMemoryRbac<MyUser, MyRole, MyPermission> = InMemoryRbac::new();
+
+    let sam = MyUser { id: 12 };
+    let frodo = MyUser { id: 14 };
+    let agent = MyRole { id: 110 };
+    let salesperson = MyRole { id: 111 };
+    let make_calls = MyPermission { id: 210 };
+    let generate_form = MyPermission { id: 212 };
+    let alter_state = MyPermission { id: 213 };
+
+    memory.assign_role(&sam, &agent).unwrap();
+    memory.assign_role(&sam, &salesperson).unwrap();
+    memory.add_permission(&agent, &make_calls).unwrap();
+    memory.add_permission(&salesperson, &generate_form).unwrap();
+
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    let results = [
+        memory.any_role_grants(&sam, &make_calls),
+        memory.any_role_grants(&sam, &alter_state),
+        memory.any_role_grants(&frodo, &make_calls),
+        memory.user_has_permission(&sam, &generate_form) == Ok(true),
+    ];
+    let after = ALLOCATIONS.load(Ordering::SeqCst);
+
+    assert_eq!(after - before, 0);
+    assert_eq!(results, [true, false, false, true]);
+}
@@ -1,6 +1,10 @@
 extern crate rbac;
 
-use std::collections::HashSet;
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasherDefault;
+use std::time::{Duration, SystemTime};
 
 use rbac::traits::{Identifiable, RbacIterators, RbacModel};
 use rbac::*;
@@ -44,11 +48,25 @@ impl Identifiable for MyPermission {
     }
 }
 
+thread_local! {
+    static NOW: Cell<SystemTime> = const { Cell::new(SystemTime::UNIX_EPOCH) };
+}
+
+// A clock for `set_clock` that only moves when `advance_clock` is called
+fn mock_now() -> SystemTime {
+    NOW.with(Cell::get)
+}
+
+fn advance_clock(by: Duration) {
+    NOW.with(|now| now.set(now.get() + by));
+}
+
 // gandalf is administrator
 // elrond is supervisor
 // sam is agent and salesperson
 // legolas is salesperson
 // frodo has no role
+#[allow(clippy::type_complexity)]
 fn test_environment() -> (
     InMemoryRbac<MyUser, MyRole, MyPermission>,
     Vec<MyUser>,
@@ -100,7 +118,7 @@ fn test_environment() -> (
         unlimited_lookups,
     ];
     for p in &permissions {
-        memory.add_permission(&roles[3], &p).unwrap();
+        memory.add_permission(&roles[3], p).unwrap();
     }
     (memory, users, roles, permissions)
 }
@@ -353,3 +371,4052 @@ fn iter_role_permission_ids() {
             .collect()
     );
 }
+
+#[test]
+fn sync_from_map() {
+    let (mut memory, users, roles, _) = test_environment();
+
+    // gandalf keeps his role, sam loses salesperson, legolas gains agent,
+    // frodo gets his first role and elrond is dropped entirely
+    let mut desired = HashMap::new();
+    desired.insert(
+        users[0].get_rbac_id(),
+        vec![roles[3].get_rbac_id()].into_iter().collect(),
+    );
+    desired.insert(
+        users[2].get_rbac_id(),
+        vec![roles[0].get_rbac_id()].into_iter().collect(),
+    );
+    desired.insert(
+        users[3].get_rbac_id(),
+        vec![roles[0].get_rbac_id(), roles[1].get_rbac_id()]
+            .into_iter()
+            .collect(),
+    );
+    desired.insert(
+        users[4].get_rbac_id(),
+        vec![roles[2].get_rbac_id()].into_iter().collect(),
+    );
+
+    assert_eq!(
+        memory.sync_from_map(&desired).unwrap(),
+        SyncReport {
+            added: 2,
+            removed: 2
+        }
+    );
+
+    assert_eq!(memory.user_has_role(&users[0], &roles[3]), Ok(true));
+    assert_eq!(memory.user_has_role(&users[1], &roles[2]), Ok(false));
+    assert_eq!(memory.user_has_role(&users[2], &roles[1]), Ok(false));
+    assert_eq!(memory.user_has_role(&users[3], &roles[0]), Ok(true));
+    assert_eq!(memory.user_has_role(&users[4], &roles[2]), Ok(true));
+
+    // Syncing the same state again changes nothing
+    assert_eq!(memory.sync_from_map(&desired), Ok(SyncReport::default()));
+
+    // An expired assignment is added again
+    memory
+        .assign_role_until(
+            &users[0],
+            &roles[3],
+            SystemTime::now() - Duration::from_secs(60),
+        )
+        .unwrap();
+    assert_eq!(
+        memory.sync_from_map(&desired).unwrap(),
+        SyncReport {
+            added: 1,
+            removed: 0
+        }
+    );
+    assert_eq!(memory.user_has_role(&users[0], &roles[3]), Ok(true));
+
+    // An assignment that is kept keeps its expiry time
+    memory.set_clock(mock_now);
+    memory
+        .assign_role_until(&users[2], &roles[0], mock_now() + Duration::from_secs(60))
+        .unwrap();
+    assert_eq!(memory.sync_from_map(&desired), Ok(SyncReport::default()));
+    advance_clock(Duration::from_secs(60));
+    assert_eq!(memory.user_has_role(&users[2], &roles[0]), Ok(false));
+}
+
+#[test]
+fn sync_from_map_constraints() {
+    let (mut memory, users, roles, _) = test_environment();
+    let mut desired = HashMap::new();
+    desired.insert(
+        users[4].get_rbac_id(),
+        vec![roles[0].get_rbac_id(), roles[2].get_rbac_id()]
+            .into_iter()
+            .collect::<HashSet<u32>>(),
+    );
+
+    // frodo can't hold agent and supervisor at once, and nothing changes
+    memory.add_mutually_exclusive_roles(&roles[0], &roles[2]);
+    assert_eq!(
+        memory.sync_from_map(&desired),
+        Err(InMemoryRbacError::ConstraintViolated(
+            ConstraintViolation::MutuallyExclusiveRole
+        ))
+    );
+    assert_eq!(memory.user_has_role(&users[0], &roles[3]), Ok(true));
+    assert_eq!(memory.user_has_role(&users[4], &roles[0]), Ok(false));
+
+    // The prerequisite of a role has to be desired as well
+    let (mut memory, _, _, _) = test_environment();
+    memory.set_role_prerequisite(&roles[2], &roles[1]);
+    assert_eq!(
+        memory.sync_from_map(&desired),
+        Err(InMemoryRbacError::MissingPrerequisiteRole)
+    );
+    desired
+        .get_mut(&users[4].get_rbac_id())
+        .unwrap()
+        .insert(roles[1].get_rbac_id());
+    assert!(memory.sync_from_map(&desired).is_ok());
+
+    // The limit counts the desired holders, not the current ones
+    let (mut memory, _, _, _) = test_environment();
+    memory.set_max_users_per_role(&roles[1], 0);
+    assert_eq!(
+        memory.sync_from_map(&desired),
+        Err(InMemoryRbacError::ConstraintViolated(
+            ConstraintViolation::MaxUsersPerRoleExceeded
+        ))
+    );
+    memory.set_max_users_per_role(&roles[1], 1);
+    assert_eq!(
+        memory.sync_from_map(&desired),
+        Ok(SyncReport {
+            added: 3,
+            removed: 5
+        })
+    );
+}
+
+#[test]
+fn permission_source() {
+    let (memory, users, roles, permissions) = test_environment();
+
+    // The permission is granted by the only role that has it
+    assert_eq!(
+        memory.permission_source(&users[2], &permissions[2]),
+        Some(PermissionSource::Role(roles[1].get_rbac_id()))
+    );
+    assert_eq!(
+        memory.permission_source(&users[2], &permissions[0]),
+        Some(PermissionSource::Role(roles[0].get_rbac_id()))
+    );
+
+    // The user has roles, but none grants the permission
+    assert_eq!(memory.permission_source(&users[2], &permissions[3]), None);
+
+    // The user has no role
+    assert_eq!(memory.permission_source(&users[4], &permissions[0]), None);
+
+    // legolas inherits the permission from agent through team_lead
+    let mut memory = memory;
+    let team_lead = MyRole { id: 115 };
+    memory.add_role_parent(&roles[1], &team_lead).unwrap();
+    memory.add_role_parent(&team_lead, &roles[0]).unwrap();
+    assert_eq!(
+        memory.permission_source(&users[3], &permissions[0]),
+        Some(PermissionSource::Inherited {
+            role: roles[1].get_rbac_id(),
+            chain: vec![team_lead.get_rbac_id(), roles[0].get_rbac_id()]
+        })
+    );
+
+    // An attached permission is preferred over an inherited one
+    memory.assign_role(&users[3], &roles[0]).unwrap();
+    assert_eq!(
+        memory.permission_source(&users[3], &permissions[0]),
+        Some(PermissionSource::Role(roles[0].get_rbac_id()))
+    );
+
+    // The permission is a member of a group that team_lead has
+    let reporting = MyPermission { id: 220 };
+    let export = MyPermission { id: 221 };
+    memory
+        .define_permission_group(&reporting, &[MyPermission { id: 221 }])
+        .unwrap();
+    memory.add_permission(&team_lead, &reporting).unwrap();
+    assert_eq!(
+        memory.permission_source(&users[3], &export),
+        Some(PermissionSource::Group {
+            role: roles[1].get_rbac_id(),
+            chain: vec![team_lead.get_rbac_id()],
+            group: reporting.get_rbac_id()
+        })
+    );
+}
+
+#[test]
+fn max_permissions_per_role() {
+    let (mut memory, _, roles, permissions) = test_environment();
+    memory.set_max_permissions_per_role(2);
+
+    // The role is below the limit
+    assert_eq!(memory.add_permission(&roles[1], &permissions[0]), Ok(true));
+
+    // The role has reached the limit
+    assert_eq!(
+        memory.add_permission(&roles[1], &permissions[1]),
+        Err(InMemoryRbacError::PermissionLimitExceeded)
+    );
+    assert_eq!(
+        memory.role_has_permission(&roles[1], &permissions[1]),
+        Ok(false)
+    );
+
+    // Re-adding a permission the full role already has
+    assert_eq!(memory.add_permission(&roles[1], &permissions[0]), Ok(false));
+
+    // A limit of zero doesn't leave an empty role behind
+    let temp_role = MyRole { id: 114 };
+    memory.set_max_permissions_per_role(0);
+    assert_eq!(
+        memory.add_permission(&temp_role, &permissions[0]),
+        Err(InMemoryRbacError::PermissionLimitExceeded)
+    );
+    assert_eq!(
+        memory.iter_role_permission_ids(&temp_role).unwrap_err(),
+        InMemoryRbacError::RoleHasNoPermissions
+    );
+}
+
+#[test]
+fn arc_snapshot() {
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    let (mut memory, users, roles, permissions) = test_environment();
+
+    let snapshot = memory.arc_snapshot();
+    let cloned = std::sync::Arc::clone(&snapshot);
+    assert_send_sync(&snapshot);
+
+    // Both handles answer checks identically
+    for u in &users {
+        for p in &permissions {
+            assert_eq!(
+                snapshot.user_has_permission(u, p),
+                cloned.user_has_permission(u, p)
+            );
+            assert_eq!(
+                snapshot.user_has_permission(u, p),
+                memory.user_has_permission(u, p)
+            );
+        }
+    }
+
+    // Changing the model and taking a new snapshot doesn't affect the old one
+    memory.assign_role(&users[4], &roles[2]).unwrap();
+    let new_snapshot = memory.arc_snapshot();
+    assert_eq!(new_snapshot.user_has_role(&users[4], &roles[2]), Ok(true));
+    assert_eq!(cloned.user_has_role(&users[4], &roles[2]), Ok(false));
+    assert_eq!(
+        cloned.iter_user_role_ids(&users[4]).unwrap_err(),
+        InMemoryRbacError::UserHasNoRoles
+    );
+}
+
+#[test]
+fn evaluate() {
+    let (memory, users, _, _) = test_environment();
+
+    let has = |id| PermExpr::Has(MyPermission { id });
+    let not = |expr| PermExpr::Not(Box::new(expr));
+
+    // sam can make calls, can't alter the state and can generate forms
+    let expr = PermExpr::And(vec![
+        has(210),
+        not(has(213)),
+        PermExpr::Or(vec![has(213), has(212)]),
+    ]);
+    assert_eq!(memory.evaluate(&users[2], &expr), Ok(true));
+
+    // None of the alternatives holds
+    let expr = PermExpr::And(vec![has(210), PermExpr::Or(vec![has(213), has(214)])]);
+    assert_eq!(memory.evaluate(&users[2], &expr), Ok(false));
+
+    // Empty conjunctions and disjunctions
+    assert_eq!(memory.evaluate(&users[2], &PermExpr::And(vec![])), Ok(true));
+    assert_eq!(memory.evaluate(&users[2], &PermExpr::Or(vec![])), Ok(false));
+
+    // The user has no role
+    assert_eq!(memory.evaluate(&users[4], &not(has(210))), Ok(true));
+}
+
+#[test]
+fn role_assigned_by() {
+    let (mut memory, users, roles, _) = test_environment();
+
+    // The assignment was recorded with a grantor
+    assert_eq!(
+        memory.assign_role_by(&users[0], &users[4], &roles[0]),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.role_assigned_by(&users[4], &roles[0]),
+        Some(users[0].get_rbac_id())
+    );
+
+    // Assigning the role again keeps the original grantor
+    assert_eq!(
+        memory.assign_role_by(&users[1], &users[4], &roles[0]),
+        Ok(false)
+    );
+    assert_eq!(
+        memory.role_assigned_by(&users[4], &roles[0]),
+        Some(users[0].get_rbac_id())
+    );
+
+    // The assignment was made without a grantor
+    assert_eq!(memory.role_assigned_by(&users[2], &roles[0]), None);
+
+    // The role has been unassigned
+    memory.unassign_role(&users[4], &roles[0]).unwrap();
+    assert_eq!(memory.role_assigned_by(&users[4], &roles[0]), None);
+}
+
+#[test]
+fn capabilities_response() {
+    let (memory, users, roles, permissions) = test_environment();
+
+    // The user has multiple roles with overlapping permissions
+    let response = memory.capabilities_response(&users[2]).unwrap();
+    assert_eq!(response.user_id, users[2].get_rbac_id());
+    assert_eq!(
+        response.roles.iter().cloned().collect::<HashSet<_>>(),
+        vec![roles[0].get_rbac_id(), roles[1].get_rbac_id()]
+            .into_iter()
+            .collect()
+    );
+    let mut granted = response.permissions.clone();
+    granted.sort_unstable();
+    assert_eq!(
+        granted,
+        vec![
+            permissions[0].get_rbac_id(),
+            permissions[1].get_rbac_id(),
+            permissions[2].get_rbac_id()
+        ]
+    );
+
+    // The user has no role
+    let response = memory.capabilities_response(&users[4]).unwrap();
+    assert!(response.roles.is_empty());
+    assert!(response.permissions.is_empty());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn capabilities_response_serialize() {
+    let (memory, users, _, _) = test_environment();
+
+    let response = memory.capabilities_response(&users[2]).unwrap();
+    let json: serde_json::Value = serde_json::to_value(&response).unwrap();
+    assert_eq!(json["user_id"], 12);
+    assert_eq!(json["roles"].as_array().unwrap().len(), 2);
+    assert_eq!(json["permissions"].as_array().unwrap().len(), 3);
+}
+
+#[test]
+fn role_is_superset_of() {
+    let (memory, _, roles, _) = test_environment();
+
+    let temp_role = MyRole { id: 114 };
+
+    // The role has all the permissions of the other and more
+    assert!(memory.role_is_superset_of(&roles[3], &roles[0]));
+
+    // The roles don't share all permissions
+    assert!(!memory.role_is_superset_of(&roles[0], &roles[3]));
+    assert!(!memory.role_is_superset_of(&roles[0], &roles[1]));
+
+    // A role is a superset of itself
+    assert!(memory.role_is_superset_of(&roles[2], &roles[2]));
+
+    // A role without permissions
+    assert!(memory.role_is_superset_of(&roles[1], &temp_role));
+    assert!(!memory.role_is_superset_of(&temp_role, &roles[1]));
+}
+
+#[test]
+fn assign_role_idempotent() {
+    let (mut memory, users, roles, _) = test_environment();
+
+    // The first delivery assigns the role
+    assert_eq!(
+        memory.assign_role_idempotent(&users[4], &roles[0], "a"),
+        Ok(true)
+    );
+
+    // A replay returns the original result even though the role was unassigned in between
+    memory.unassign_role(&users[4], &roles[0]).unwrap();
+    assert_eq!(
+        memory.assign_role_idempotent(&users[4], &roles[0], "a"),
+        Ok(true)
+    );
+    assert_eq!(memory.user_has_role(&users[4], &roles[0]), Ok(false));
+
+    // A new key for an existing assignment
+    assert_eq!(
+        memory.assign_role_idempotent(&users[0], &roles[3], "b"),
+        Ok(false)
+    );
+
+    // The oldest key is forgotten once the capacity is exceeded
+    memory.set_idempotency_key_capacity(2);
+    assert_eq!(
+        memory.assign_role_idempotent(&users[4], &roles[1], "c"),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.assign_role_idempotent(&users[4], &roles[0], "a"),
+        Ok(true)
+    );
+    assert_eq!(memory.user_has_role(&users[4], &roles[0]), Ok(true));
+}
+
+#[test]
+fn user_permissions_by_role() {
+    let (memory, users, roles, permissions) = test_environment();
+
+    // The user has multiple roles
+    let mut by_role = memory.user_permissions_by_role(&users[2]).unwrap();
+    assert_eq!(by_role.len(), 2);
+    let mut agent = by_role.remove(&roles[0].get_rbac_id()).unwrap();
+    agent.sort_unstable();
+    assert_eq!(
+        agent,
+        vec![permissions[0].get_rbac_id(), permissions[1].get_rbac_id()]
+    );
+    assert_eq!(
+        by_role.remove(&roles[1].get_rbac_id()),
+        Some(vec![permissions[2].get_rbac_id()])
+    );
+
+    // The user has no role
+    assert!(memory
+        .user_permissions_by_role(&users[4])
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn user_has_permission_by_id() {
+    let (memory, users, _, permissions) = test_environment();
+
+    for u in &users {
+        for p in &permissions {
+            assert_eq!(
+                memory.user_has_permission(u, p),
+                memory.user_has_permission(u, p.get_rbac_id())
+            );
+        }
+    }
+
+    assert_eq!(memory.user_has_permission(&users[3], 212), Ok(true));
+    assert_eq!(memory.user_has_permission(&users[3], 213), Ok(false));
+}
+
+#[test]
+fn check_fixed() {
+    let (memory, users, _, permissions) = test_environment();
+
+    // sam can make calls and generate forms, but not alter the state
+    assert_eq!(
+        memory.check_fixed(
+            &users[2],
+            [&permissions[0], &permissions[3], &permissions[2]]
+        ),
+        Ok([true, false, true])
+    );
+
+    // The user has no roles
+    assert_eq!(
+        memory.check_fixed(&users[4], [&permissions[0]]),
+        Ok([false])
+    );
+}
+
+#[test]
+fn clone_role_filtered() {
+    let (mut memory, _, roles, permissions) = test_environment();
+
+    // Keep only the permissions of supervisor with even ids
+    let reduced = MyRole { id: 114 };
+    assert_eq!(
+        memory.clone_role_filtered(&roles[2], &reduced, |p| p % 2 == 0),
+        2
+    );
+    let cloned: HashSet<_> = memory.iter_role_permission_ids(&reduced).unwrap().collect();
+    assert_eq!(
+        cloned,
+        vec![permissions[0].get_rbac_id(), permissions[2].get_rbac_id()]
+            .into_iter()
+            .collect()
+    );
+
+    // The source role is untouched
+    assert_eq!(
+        memory.iter_role_permission_ids(&roles[2]).unwrap().count(),
+        4
+    );
+
+    // Permissions the target already has aren't counted
+    assert_eq!(
+        memory.clone_role_filtered(&roles[2], &roles[0], |_| true),
+        2
+    );
+
+    // Nothing matches the filter
+    let empty = MyRole { id: 115 };
+    assert_eq!(memory.clone_role_filtered(&roles[2], &empty, |_| false), 0);
+    assert_eq!(
+        memory.iter_role_permission_ids(&empty).unwrap_err(),
+        InMemoryRbacError::RoleHasNoPermissions
+    );
+}
+
+#[test]
+fn permission_popularity() {
+    let (memory, _, _, permissions) = test_environment();
+
+    let popularity = memory.permission_popularity();
+    assert_eq!(popularity.len(), 5);
+    assert!(popularity.windows(2).all(|w| w[0].1 >= w[1].1));
+
+    let rank = |p: &MyPermission| {
+        popularity
+            .iter()
+            .position(|(id, _)| *id == p.get_rbac_id())
+            .unwrap()
+    };
+
+    // make_calls is granted by agent, supervisor and administrator
+    assert!(popularity.contains(&(permissions[0].get_rbac_id(), 3)));
+
+    // unlimited_lookups is only granted by administrator
+    assert_eq!(popularity[4], (permissions[4].get_rbac_id(), 1));
+    assert!(rank(&permissions[0]) < rank(&permissions[4]));
+}
+
+#[test]
+fn from_entities() {
+    let (memory, users, roles, permissions) = test_environment();
+
+    let assignments = vec![
+        (&users[0], &roles[3]),
+        (&users[1], &roles[2]),
+        (&users[2], &roles[0]),
+        (&users[2], &roles[1]),
+        (&users[3], &roles[1]),
+        // Duplicates are ignored
+        (&users[3], &roles[1]),
+    ];
+    let mut grants = vec![
+        (&roles[0], &permissions[0]),
+        (&roles[0], &permissions[1]),
+        (&roles[1], &permissions[2]),
+        (&roles[2], &permissions[0]),
+        (&roles[2], &permissions[1]),
+        (&roles[2], &permissions[2]),
+        (&roles[2], &permissions[3]),
+    ];
+    grants.extend(permissions.iter().map(|p| (&roles[3], p)));
+
+    let built: InMemoryRbac<MyUser, MyRole, MyPermission> =
+        InMemoryRbac::from_entities(assignments, grants);
+
+    for u in &users {
+        for r in &roles {
+            assert_eq!(built.user_has_role(u, r), memory.user_has_role(u, r));
+        }
+        for p in &permissions {
+            assert_eq!(
+                built.user_has_permission(u, p),
+                memory.user_has_permission(u, p)
+            );
+        }
+    }
+    assert_eq!(built.iter_user_role_ids(&users[3]).unwrap().count(), 1);
+}
+
+#[test]
+fn from_assignments() {
+    let (_, users, roles, permissions) = test_environment();
+
+    // Duplicates are ignored
+    let assignments = vec![(12, 110), (12, 111), (13, 111), (13, 111)];
+    let grants = vec![(110, 210), (110, 211), (111, 212), (111, 212)];
+    let built: InMemoryRbac<MyUser, MyRole, MyPermission> =
+        InMemoryRbac::from_assignments(assignments, grants);
+
+    assert_eq!(
+        built.user_has_permission(&users[2], &permissions[0]),
+        Ok(true)
+    );
+    assert_eq!(
+        built.user_has_permission(&users[3], &permissions[2]),
+        Ok(true)
+    );
+    assert_eq!(
+        built.user_has_permission(&users[3], &permissions[0]),
+        Ok(false)
+    );
+    assert_eq!(built.iter_user_role_ids(&users[3]).unwrap().count(), 1);
+    assert_eq!(
+        built.iter_role_permission_ids(&roles[1]).unwrap().count(),
+        1
+    );
+}
+
+#[test]
+fn snapshot_diff() {
+    let (mut memory, users, roles, permissions) = test_environment();
+    let snapshot = memory.snapshot();
+
+    // Nothing has changed
+    assert!(memory.diff(&snapshot).is_empty());
+
+    memory.assign_role(&users[4], &roles[0]).unwrap();
+    memory.unassign_role(&users[2], &roles[1]).unwrap();
+    memory.add_permission(&roles[1], &permissions[4]).unwrap();
+    memory
+        .remove_permission(&roles[0], &permissions[1])
+        .unwrap();
+    // Changes that are undone don't show up
+    memory.assign_role(&users[3], &roles[2]).unwrap();
+    memory.unassign_role(&users[3], &roles[2]).unwrap();
+
+    let diff = memory.diff(&snapshot);
+    assert_eq!(diff.assigned, vec![(14, 110)]);
+    assert_eq!(diff.unassigned, vec![(12, 111)]);
+    assert_eq!(diff.added_permissions, vec![(111, 214)]);
+    assert_eq!(diff.removed_permissions, vec![(110, 211)]);
+
+    // Applying the changes to the snapshotted state yields the current state
+    let (mut replica, _, _, _) = test_environment();
+    for change in diff.into_changes() {
+        assert_eq!(replica.apply_change(change), Ok(true));
+    }
+    assert_eq!(replica.fingerprint(), memory.fingerprint());
+}
+
+#[test]
+fn merge() {
+    let (_, users, roles, permissions) = test_environment();
+
+    let mut memory: InMemoryRbac<MyUser, MyRole, MyPermission> =
+        InMemoryRbac::from_assignments(vec![(12, 110)], vec![(110, 210)]);
+    let other = InMemoryRbac::from_assignments(vec![(12, 111), (13, 111)], vec![(110, 211)]);
+    memory.merge(other);
+
+    // The permissions of the same role are combined
+    assert_eq!(
+        memory.user_has_permission(&users[2], &permissions[0]),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.user_has_permission(&users[2], &permissions[1]),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.iter_role_permission_ids(&roles[0]).unwrap().count(),
+        2
+    );
+
+    // The roles of the same user are combined
+    assert_eq!(memory.user_has_role(&users[2], &roles[0]), Ok(true));
+    assert_eq!(memory.user_has_role(&users[2], &roles[1]), Ok(true));
+
+    // Users only in the other model carry over
+    assert_eq!(memory.user_has_role(&users[3], &roles[1]), Ok(true));
+    assert_eq!(memory.user_count(), 2);
+}
+
+#[test]
+fn merge_expiry() {
+    let (_, users, roles, permissions) = test_environment();
+    let past = SystemTime::now() - Duration::from_secs(60);
+
+    let mut memory: InMemoryRbac<MyUser, MyRole, MyPermission> =
+        InMemoryRbac::from_assignments(vec![(12, 110)], vec![(110, 210), (111, 212)]);
+    memory
+        .assign_role_until(&users[3], &roles[1], past)
+        .unwrap();
+    let mut other = InMemoryRbac::from_assignments(vec![(13, 111)], vec![]);
+    other.assign_role_until(&users[4], &roles[0], past).unwrap();
+    other.assign_role_until(&users[2], &roles[0], past).unwrap();
+    memory.merge(other);
+
+    // An expired assignment of the other model stays expired
+    assert_eq!(
+        memory.user_has_permission(&users[4], &permissions[0]),
+        Ok(false)
+    );
+
+    // An assignment in both models lasts as long as the longer one
+    assert_eq!(
+        memory.user_has_permission(&users[2], &permissions[0]),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.user_has_permission(&users[3], &permissions[2]),
+        Ok(true)
+    );
+}
+
+#[test]
+fn permission_symmetric_difference() {
+    let (mut memory, users, _, permissions) = test_environment();
+
+    // sam has everything legolas has and more
+    assert_eq!(
+        memory.permission_symmetric_difference(&users[2], &users[3]),
+        Ok(
+            vec![permissions[0].get_rbac_id(), permissions[1].get_rbac_id()]
+                .into_iter()
+                .collect()
+        )
+    );
+
+    // Both users have permissions the other one lacks
+    let temp_user = MyUser { id: 15 };
+    let temp_role = MyRole { id: 114 };
+    memory.assign_role(&temp_user, &temp_role).unwrap();
+    memory.add_permission(&temp_role, &permissions[3]).unwrap();
+    assert_eq!(
+        memory.permission_symmetric_difference(&temp_user, &users[3]),
+        Ok(
+            vec![permissions[2].get_rbac_id(), permissions[3].get_rbac_id()]
+                .into_iter()
+                .collect()
+        )
+    );
+
+    // The users have the same permissions
+    assert_eq!(
+        memory.permission_symmetric_difference(&users[0], &users[0]),
+        Ok(HashSet::new())
+    );
+
+    // One user has no role
+    assert_eq!(
+        memory
+            .permission_symmetric_difference(&users[4], &users[3])
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct MyTenant {
+    id: u32,
+}
+
+impl Identifiable for MyTenant {
+    type Id = u32;
+
+    fn get_rbac_id(&self) -> Self::Id {
+        self.id
+    }
+}
+
+#[test]
+fn multi_tenant() {
+    let (_, users, roles, permissions) = test_environment();
+    let mut tenants: MultiTenantRbac<MyTenant, MyUser, MyRole, MyPermission> =
+        MultiTenantRbac::new();
+
+    let shire = MyTenant { id: 1 };
+    let rivendell = MyTenant { id: 2 };
+
+    tenants.assign_role(&shire, &users[2], &roles[0]).unwrap();
+    tenants
+        .add_permission(&shire, &roles[0], &permissions[0])
+        .unwrap();
+
+    // The assignment is visible in the tenant it was made in
+    assert_eq!(
+        tenants.user_has_permission(&shire, &users[2], &permissions[0]),
+        Ok(true)
+    );
+    assert_eq!(
+        tenants.user_has_role(&shire, &users[2], &roles[0]),
+        Ok(true)
+    );
+
+    // The other tenant doesn't exist yet
+    assert!(tenants.tenant(&rivendell).is_none());
+    assert_eq!(
+        tenants.user_has_permission(&rivendell, &users[2], &permissions[0]),
+        Ok(false)
+    );
+
+    // The role grants the permission in the other tenant, but the user isn't assigned there
+    tenants
+        .add_permission(&rivendell, &roles[0], &permissions[0])
+        .unwrap();
+    assert!(tenants.tenant(&rivendell).is_some());
+    assert_eq!(
+        tenants.user_has_permission(&rivendell, &users[2], &permissions[0]),
+        Ok(false)
+    );
+
+    // Removing from one tenant leaves the other untouched
+    assert_eq!(
+        tenants.remove_permission(&rivendell, &roles[0], &permissions[0]),
+        Ok(true)
+    );
+    assert_eq!(
+        tenants.user_has_permission(&shire, &users[2], &permissions[0]),
+        Ok(true)
+    );
+    assert_eq!(
+        tenants.unassign_role(&rivendell, &users[2], &roles[0]),
+        Ok(false)
+    );
+}
+
+#[test]
+fn iter_user_role_ids_exact() {
+    let (memory, users, _, _) = test_environment();
+
+    // The user has multiple roles
+    let iter = memory.iter_user_role_ids_exact(&users[2]).unwrap();
+    assert_eq!(iter.len(), 2);
+    let mut roles = Vec::with_capacity(iter.len());
+    roles.extend(iter);
+    assert_eq!(roles.len(), 2);
+
+    // The user has no role
+    assert_eq!(
+        memory.iter_user_role_ids_exact(&users[4]).err(),
+        Some(InMemoryRbacError::UserHasNoRoles)
+    );
+}
+
+#[test]
+fn iter_user_role_ids_all() {
+    let (mut memory, users, roles, _) = test_environment();
+    memory
+        .assign_role_until(
+            &users[3],
+            &roles[0],
+            SystemTime::now() - Duration::from_secs(60),
+        )
+        .unwrap();
+
+    // The expired role is only yielded by the `_all` variant
+    let active: HashSet<u32> = memory.iter_user_role_ids(&users[3]).unwrap().collect();
+    assert_eq!(active, vec![roles[1].get_rbac_id()].into_iter().collect());
+    let all: HashSet<u32> = memory.iter_user_role_ids_all(&users[3]).unwrap().collect();
+    assert_eq!(
+        all,
+        vec![roles[0].get_rbac_id(), roles[1].get_rbac_id()]
+            .into_iter()
+            .collect()
+    );
+
+    // A user with only an expired role still has it
+    memory
+        .assign_role_until(
+            &users[4],
+            &roles[0],
+            SystemTime::now() - Duration::from_secs(60),
+        )
+        .unwrap();
+    assert_eq!(
+        memory.iter_user_role_ids(&users[4]).err(),
+        Some(InMemoryRbacError::UserHasNoRoles)
+    );
+    assert_eq!(memory.iter_user_role_ids_all(&users[4]).unwrap().count(), 1);
+
+    // The user has no role
+    assert_eq!(
+        memory.iter_user_role_ids_all(&MyUser { id: 15 }).err(),
+        Some(InMemoryRbacError::UserHasNoRoles)
+    );
+}
+
+#[test]
+fn borrowed_iterators() {
+    let (mut memory, users, roles, _) = test_environment();
+
+    // The iterators borrow from the model and know how many permissions are left
+    let user_roles: UserRoleIds<u32> = memory.iter_user_role_ids(&users[2]).unwrap();
+    assert_eq!(user_roles.count(), 2);
+    let permissions: RolePermissionIds<u32> = memory.iter_role_permission_ids(&roles[0]).unwrap();
+    assert_eq!(permissions.len(), 2);
+
+    // Inherited permissions are included, with or without materializing the hierarchy
+    memory.add_role_parent(&roles[1], &roles[0]).unwrap();
+    assert_eq!(memory.iter_role_permission_ids(&roles[1]).unwrap().len(), 3);
+    memory.materialize_inheritance().unwrap();
+    assert_eq!(memory.iter_role_permission_ids(&roles[1]).unwrap().len(), 3);
+}
+
+#[test]
+fn permission_prerequisite() {
+    let (mut memory, users, roles, permissions) = test_environment();
+
+    let view = MyPermission { id: 215 };
+    let approve = MyPermission { id: 216 };
+    let temp_role = MyRole { id: 114 };
+    memory.assign_role(&users[4], &temp_role).unwrap();
+    memory.add_permission(&temp_role, &approve).unwrap();
+    memory.add_permission(&roles[3], &approve).unwrap();
+    memory.add_permission(&roles[3], &view).unwrap();
+
+    // Without a prerequisite the permission is effective
+    assert_eq!(memory.user_has_permission(&users[4], &approve), Ok(true));
+
+    // The user lacks the prerequisite
+    memory.set_permission_prerequisite(&approve, &view).unwrap();
+    assert_eq!(memory.user_has_permission(&users[4], &approve), Ok(false));
+    assert_eq!(memory.permission_source(&users[4], &approve), None);
+
+    // The user has the prerequisite
+    assert_eq!(memory.user_has_permission(&users[0], &approve), Ok(true));
+
+    // Prerequisites are checked transitively
+    memory
+        .set_permission_prerequisite(&view, &permissions[3])
+        .unwrap();
+    assert_eq!(memory.user_has_permission(&users[0], &approve), Ok(true));
+    memory
+        .remove_permission(&roles[3], &permissions[3])
+        .unwrap();
+    assert_eq!(memory.user_has_permission(&users[0], &approve), Ok(false));
+    assert!(!memory
+        .capabilities_response(&users[0])
+        .unwrap()
+        .permissions
+        .contains(&approve.get_rbac_id()));
+
+    // Cycles are rejected
+    assert_eq!(
+        memory.set_permission_prerequisite(&permissions[3], &approve),
+        Err(InMemoryRbacError::PrerequisiteCycle)
+    );
+    assert_eq!(
+        memory.set_permission_prerequisite(&view, &view),
+        Err(InMemoryRbacError::PrerequisiteCycle)
+    );
+
+    // Removing the prerequisite makes the permission effective again
+    assert!(memory.remove_permission_prerequisite(&approve));
+    assert_eq!(memory.user_has_permission(&users[4], &approve), Ok(true));
+}
+
+#[test]
+fn fingerprint() {
+    let (mut memory, users, roles, permissions) = test_environment();
+
+    // The same contents inserted in a different order
+    let mut other: InMemoryRbac<MyUser, MyRole, MyPermission> = InMemoryRbac::new();
+    for p in permissions.iter().rev() {
+        other.add_permission(&roles[3], p).unwrap();
+    }
+    for &(r, p) in &[(2, 3), (2, 2), (2, 1), (2, 0), (1, 2), (0, 1), (0, 0)] {
+        other.add_permission(&roles[r], &permissions[p]).unwrap();
+    }
+    for &(u, r) in &[(3, 1), (2, 1), (2, 0), (1, 2), (0, 3)] {
+        other.assign_role(&users[u], &roles[r]).unwrap();
+    }
+    assert_eq!(memory.fingerprint(), other.fingerprint());
+
+    // A single change is detected
+    other.unassign_role(&users[2], &roles[1]).unwrap();
+    assert_ne!(memory.fingerprint(), other.fingerprint());
+    other.assign_role(&users[2], &roles[1]).unwrap();
+    assert_eq!(memory.fingerprint(), other.fingerprint());
+
+    // Adding a grant is detected as well
+    let before = memory.fingerprint();
+    memory.add_permission(&roles[0], &permissions[2]).unwrap();
+    assert_ne!(memory.fingerprint(), before);
+
+    // Denied permissions and expiry times are part of the fingerprint
+    let before = memory.fingerprint();
+    memory.deny_permission(&users[2], &permissions[0]);
+    assert_ne!(memory.fingerprint(), before);
+    memory.allow_permission(&users[2], &permissions[0]);
+    assert_eq!(memory.fingerprint(), before);
+    memory
+        .assign_role_until(
+            &users[2],
+            &roles[0],
+            SystemTime::now() + Duration::from_secs(60),
+        )
+        .unwrap();
+    assert_ne!(memory.fingerprint(), before);
+}
+
+#[test]
+fn dangling_assignments() {
+    let (mut memory, users, roles, _) = test_environment();
+
+    // All assigned roles are known
+    assert!(memory.dangling_assignments(&roles).is_empty());
+
+    // A user has a role that isn't known
+    let deleted_role = MyRole { id: 114 };
+    memory.assign_role(&users[2], &deleted_role).unwrap();
+    assert_eq!(
+        memory.dangling_assignments(&roles),
+        vec![(users[2].get_rbac_id(), deleted_role.get_rbac_id())]
+    );
+
+    // Without a catalog every assignment is dangling
+    assert_eq!(memory.dangling_assignments(&[]).len(), 6);
+}
+
+#[test]
+fn which_users_have() {
+    let (memory, users, _, permissions) = test_environment();
+
+    // Everyone except frodo can generate forms
+    let expected: HashMap<_, _> = vec![(10, true), (11, true), (12, true), (13, true), (14, false)]
+        .into_iter()
+        .collect();
+    assert_eq!(
+        memory.which_users_have(&users, &permissions[2]),
+        Ok(expected)
+    );
+
+    // No users to check
+    assert_eq!(
+        memory.which_users_have(&[], &permissions[2]),
+        Ok(HashMap::new())
+    );
+}
+
+enum CallCenterPermission {
+    MakeCalls,
+    EnterInformation,
+    AlterState,
+}
+
+impl Identifiable for CallCenterPermission {
+    type Id = u32;
+
+    fn get_rbac_id(&self) -> Self::Id {
+        match self {
+            CallCenterPermission::MakeCalls => 210,
+            CallCenterPermission::EnterInformation => 211,
+            CallCenterPermission::AlterState => 213,
+        }
+    }
+}
+
+#[test]
+fn match_permissions() {
+    let (_, users, roles, _) = test_environment();
+
+    let mut memory: InMemoryRbac<MyUser, MyRole, CallCenterPermission> = InMemoryRbac::new();
+    memory.assign_role(&users[2], &roles[0]).unwrap();
+    memory
+        .add_permission(&roles[0], &CallCenterPermission::MakeCalls)
+        .unwrap();
+    memory
+        .add_permission(&roles[0], &CallCenterPermission::EnterInformation)
+        .unwrap();
+
+    // The user has some of the permissions
+    let caps = match_permissions!(&memory, &users[2], {
+        can_make_calls: CallCenterPermission::MakeCalls,
+        can_enter_information: CallCenterPermission::EnterInformation,
+        can_alter_state: CallCenterPermission::AlterState,
+    })
+    .unwrap();
+    assert!(caps.can_make_calls);
+    assert!(caps.can_enter_information);
+    assert!(!caps.can_alter_state);
+
+    // The user has no role
+    let caps = match_permissions!(&memory, &users[4], {
+        can_make_calls: CallCenterPermission::MakeCalls,
+    })
+    .unwrap();
+    assert!(!caps.can_make_calls);
+}
+
+#[test]
+fn export_import_catalog() {
+    let (mut memory, users, roles, permissions) = test_environment();
+
+    let catalog = memory.export_catalog();
+    assert_eq!(catalog.roles.len(), 4);
+    assert_eq!(catalog.roles[&roles[2].get_rbac_id()].len(), 4);
+
+    // Change both the role definitions and the assignments
+    memory
+        .remove_permission(&roles[1], &permissions[2])
+        .unwrap();
+    memory
+        .add_permission(&MyRole { id: 114 }, &permissions[0])
+        .unwrap();
+    memory.assign_role(&users[4], &roles[1]).unwrap();
+
+    // Importing restores the role definitions and keeps the assignments
+    memory.import_catalog(catalog.clone());
+    assert_eq!(memory.export_catalog(), catalog);
+    assert_eq!(
+        memory.role_has_permission(&roles[1], &permissions[2]),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.role_has_permission(&MyRole { id: 114 }, &permissions[0]),
+        Ok(false)
+    );
+    assert_eq!(memory.user_has_role(&users[4], &roles[1]), Ok(true));
+    assert_eq!(memory.user_has_role(&users[2], &roles[0]), Ok(true));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn export_import_catalog_serialize() {
+    let (mut memory, users, roles, permissions) = test_environment();
+
+    let json = serde_json::to_string(&memory.export_catalog()).unwrap();
+    memory
+        .remove_permission(&roles[0], &permissions[0])
+        .unwrap();
+    memory.import_catalog(serde_json::from_str(&json).unwrap());
+    assert_eq!(
+        memory.user_has_permission(&users[2], &permissions[0]),
+        Ok(true)
+    );
+}
+
+#[test]
+fn impact_of_removing_role() {
+    let (mut memory, users, roles, permissions) = test_environment();
+
+    // Only elrond can alter the state through supervisor
+    assert_eq!(
+        memory.impact_of_removing_role(&roles[2], &permissions[3..4]),
+        Ok(vec![users[1].get_rbac_id()])
+    );
+
+    // sam can still generate forms through salesperson
+    memory.assign_role(&users[2], &roles[2]).unwrap();
+    assert_eq!(
+        memory.impact_of_removing_role(&roles[2], &permissions[2..3]),
+        Ok(vec![users[1].get_rbac_id()])
+    );
+
+    // Nobody has the role
+    let temp_role = MyRole { id: 114 };
+    assert_eq!(
+        memory.impact_of_removing_role(&temp_role, &permissions),
+        Ok(vec![])
+    );
+
+    // Nothing is required
+    assert_eq!(memory.impact_of_removing_role(&roles[2], &[]), Ok(vec![]));
+}
+
+#[test]
+fn audit_for_user() {
+    let (mut memory, users, roles, permissions) = test_environment();
+
+    // Nothing is recorded before the audit log is enabled
+    assert!(memory.audit_for_user(&users[0]).is_empty());
+
+    memory.enable_audit(3);
+    memory.assign_role(&users[4], &roles[0]).unwrap();
+    memory.unassign_role(&users[2], &roles[1]).unwrap();
+    memory.unassign_role(&users[4], &roles[0]).unwrap();
+
+    // Changes that have no effect aren't recorded
+    memory.unassign_role(&users[4], &roles[0]).unwrap();
+
+    let entries = memory.audit_for_user(&users[4]);
+    assert_eq!(
+        entries.iter().map(|e| e.action.clone()).collect::<Vec<_>>(),
+        vec![
+            RbacChange::AssignRole {
+                user: users[4].get_rbac_id(),
+                role: roles[0].get_rbac_id()
+            },
+            RbacChange::UnassignRole {
+                user: users[4].get_rbac_id(),
+                role: roles[0].get_rbac_id()
+            },
+        ]
+    );
+    assert!(entries[0].timestamp <= entries[1].timestamp);
+    assert_eq!(memory.audit_for_user(&users[2]).len(), 1);
+
+    // Changes to permissions don't touch any user, but still take up space
+    memory.add_permission(&roles[1], &permissions[0]).unwrap();
+    let entries = memory.audit_for_user(&users[4]);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(
+        entries[0].action,
+        RbacChange::UnassignRole {
+            user: users[4].get_rbac_id(),
+            role: roles[0].get_rbac_id()
+        }
+    );
+    assert_eq!(memory.audit_for_user(&users[2]).len(), 1);
+    memory.add_permission(&roles[1], &permissions[1]).unwrap();
+    assert!(memory.audit_for_user(&users[2]).is_empty());
+}
+
+#[test]
+fn to_bitmatrix() {
+    let (memory, _, roles, permissions) = test_environment();
+
+    assert_eq!(
+        memory.to_bitmatrix(&roles, &permissions),
+        vec![
+            vec![true, true, false, false, false],
+            vec![false, false, true, false, false],
+            vec![true, true, true, true, false],
+            vec![true, true, true, true, true],
+        ]
+    );
+
+    // The ordering of the caller is used
+    let temp_role = MyRole { id: 114 };
+    assert_eq!(
+        memory.to_bitmatrix(&[temp_role], &permissions[2..4]),
+        vec![vec![false, false]]
+    );
+    assert_eq!(
+        memory.to_bitmatrix(
+            &roles[1..3],
+            &[MyPermission { id: 213 }, MyPermission { id: 212 }]
+        ),
+        vec![vec![false, true], vec![true, true]]
+    );
+}
+
+#[test]
+fn ensure_permission() {
+    fn handler(
+        memory: &InMemoryRbac<MyUser, MyRole, MyPermission>,
+        user: &MyUser,
+        permission: &MyPermission,
+    ) -> Result<&'static str, Box<dyn std::error::Error>> {
+        memory.ensure_permission(user, permission)?;
+        Ok("done")
+    }
+
+    let (memory, users, _, permissions) = test_environment();
+
+    // The user has the permission
+    assert_eq!(memory.ensure_permission(&users[2], &permissions[0]), Ok(()));
+    assert_eq!(
+        handler(&memory, &users[2], &permissions[0]).unwrap(),
+        "done"
+    );
+
+    // The user doesn't have the permission
+    let err = memory
+        .ensure_permission(&users[2], &permissions[3])
+        .unwrap_err();
+    assert_eq!(
+        err,
+        AccessDenied {
+            user: users[2].get_rbac_id(),
+            permission: permissions[3].get_rbac_id()
+        }
+    );
+    assert_eq!(
+        handler(&memory, &users[2], &permissions[3])
+            .unwrap_err()
+            .to_string(),
+        "user 12 doesn't have permission 213"
+    );
+}
+
+#[test]
+fn minimal_model_for() {
+    let (mut memory, users, roles, permissions) = test_environment();
+
+    // The permission is granted by one of two roles
+    let minimal = memory.minimal_model_for(&users[2], &permissions[2]);
+    assert_eq!(
+        minimal.user_has_permission(&users[2], &permissions[2]),
+        Ok(true)
+    );
+    assert_eq!(
+        minimal
+            .iter_user_role_ids(&users[2])
+            .unwrap()
+            .collect::<Vec<_>>(),
+        vec![roles[1].get_rbac_id()]
+    );
+    assert_eq!(
+        minimal.iter_role_permission_ids(&roles[1]).unwrap().count(),
+        1
+    );
+    assert_eq!(
+        minimal.iter_user_role_ids(&users[0]).unwrap_err(),
+        InMemoryRbacError::UserHasNoRoles
+    );
+
+    // The permission isn't granted
+    let minimal = memory.minimal_model_for(&users[2], &permissions[3]);
+    assert_eq!(
+        minimal.user_has_permission(&users[2], &permissions[3]),
+        Ok(false)
+    );
+    assert_eq!(
+        minimal.iter_user_role_ids(&users[2]).unwrap_err(),
+        InMemoryRbacError::UserHasNoRoles
+    );
+
+    // The permission is denied because a prerequisite is missing
+    memory
+        .set_permission_prerequisite(&permissions[2], &permissions[3])
+        .unwrap();
+    let minimal = memory.minimal_model_for(&users[2], &permissions[2]);
+    assert_eq!(
+        minimal.user_has_permission(&users[2], &permissions[2]),
+        Ok(false)
+    );
+    let minimal = memory.minimal_model_for(&users[0], &permissions[2]);
+    assert_eq!(
+        minimal.user_has_permission(&users[0], &permissions[2]),
+        Ok(true)
+    );
+    assert_eq!(
+        minimal.iter_role_permission_ids(&roles[3]).unwrap().count(),
+        2
+    );
+
+    // Denials are copied
+    memory.deny_permission(&users[0], &permissions[3]);
+    let minimal = memory.minimal_model_for(&users[0], &permissions[2]);
+    assert_eq!(
+        minimal.user_has_permission(&users[0], &permissions[2]),
+        Ok(false)
+    );
+    memory.allow_permission(&users[0], &permissions[3]);
+
+    // Expired roles are left out and expiry times are copied
+    let until = SystemTime::now() + Duration::from_secs(60);
+    memory
+        .assign_role_until(
+            &users[4],
+            &roles[0],
+            SystemTime::now() - Duration::from_secs(60),
+        )
+        .unwrap();
+    memory
+        .assign_role_until(&users[4], &roles[3], until)
+        .unwrap();
+    let minimal = memory.minimal_model_for(&users[4], &permissions[0]);
+    assert_eq!(
+        minimal
+            .iter_user_role_ids(&users[4])
+            .unwrap()
+            .collect::<Vec<_>>(),
+        vec![roles[3].get_rbac_id()]
+    );
+    let mut expected = InMemoryRbac::new();
+    expected.add_permission(&roles[3], &permissions[0]).unwrap();
+    expected
+        .assign_role_until(&users[4], &roles[3], until)
+        .unwrap();
+    assert!(minimal == expected);
+
+    // Emergency overrides are copied
+    memory.emergency_override(&users[4], until);
+    let minimal = memory.minimal_model_for(&users[4], &permissions[4]);
+    assert_eq!(
+        minimal.user_has_permission(&users[4], &permissions[4]),
+        Ok(true)
+    );
+}
+
+#[test]
+fn user_has_permission_cached() {
+    let (mut memory, users, roles, permissions) = test_environment();
+    let mut cache = CheckCache::new();
+
+    // The first check fills the cache
+    assert_eq!(
+        memory.user_has_permission_cached(&mut cache, &users[2], &permissions[0]),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.user_has_permission_cached(&mut cache, &users[4], &permissions[0]),
+        Ok(false)
+    );
+    assert_eq!(cache.len(), 2);
+
+    // Within the same scope the cached result is reused, even though the model changed
+    memory.assign_role(&users[4], &roles[0]).unwrap();
+    assert_eq!(
+        memory.user_has_permission_cached(&mut cache, &users[4], &permissions[0]),
+        Ok(false)
+    );
+    assert_eq!(cache.len(), 2);
+
+    // A new scope sees the change
+    cache.clear();
+    assert!(cache.is_empty());
+    assert_eq!(
+        memory.user_has_permission_cached(&mut cache, &users[4], &permissions[0]),
+        Ok(true)
+    );
+}
+
+#[test]
+fn roles_granting_unknown_permissions() {
+    let (mut memory, _, roles, permissions) = test_environment();
+
+    // All granted permissions are known
+    assert!(memory
+        .roles_granting_unknown_permissions(&permissions)
+        .is_empty());
+
+    // A role grants a permission that isn't known
+    let removed_permission = MyPermission { id: 215 };
+    memory
+        .add_permission(&roles[1], &removed_permission)
+        .unwrap();
+    assert_eq!(
+        memory.roles_granting_unknown_permissions(&permissions),
+        vec![(roles[1].get_rbac_id(), removed_permission.get_rbac_id())]
+    );
+
+    // Without a catalog every grant is unknown
+    assert_eq!(memory.roles_granting_unknown_permissions(&[]).len(), 13);
+}
+
+#[test]
+fn apply_change() {
+    let (_, users, roles, permissions) = test_environment();
+
+    // The source model is built with the regular methods
+    let mut source: InMemoryRbac<MyUser, MyRole, MyPermission> = InMemoryRbac::new();
+    source.assign_role(&users[2], &roles[0]).unwrap();
+    source.assign_role(&users[2], &roles[1]).unwrap();
+    source.assign_role(&users[3], &roles[1]).unwrap();
+    source.add_permission(&roles[0], &permissions[0]).unwrap();
+    source.add_permission(&roles[1], &permissions[2]).unwrap();
+    source.add_permission(&roles[1], &permissions[3]).unwrap();
+    source.unassign_role(&users[2], &roles[1]).unwrap();
+    source
+        .remove_permission(&roles[1], &permissions[3])
+        .unwrap();
+
+    // The replica receives the same changes from a feed
+    let changes = vec![
+        RbacChange::AssignRole {
+            user: 12,
+            role: 110,
+        },
+        RbacChange::AssignRole {
+            user: 12,
+            role: 111,
+        },
+        RbacChange::AssignRole {
+            user: 13,
+            role: 111,
+        },
+        RbacChange::AddPermission {
+            role: 110,
+            permission: 210,
+        },
+        RbacChange::AddPermission {
+            role: 111,
+            permission: 212,
+        },
+        RbacChange::AddPermission {
+            role: 111,
+            permission: 213,
+        },
+        RbacChange::UnassignRole {
+            user: 12,
+            role: 111,
+        },
+        RbacChange::RemovePermission {
+            role: 111,
+            permission: 213,
+        },
+    ];
+    let mut replica: InMemoryRbac<MyUser, MyRole, MyPermission> = InMemoryRbac::new();
+    for change in changes {
+        assert_eq!(replica.apply_change(change), Ok(true));
+    }
+    assert_eq!(replica.fingerprint(), source.fingerprint());
+
+    // Changes without an effect
+    assert_eq!(
+        replica.apply_change(RbacChange::AssignRole {
+            user: 13,
+            role: 111
+        }),
+        Ok(false)
+    );
+    assert_eq!(
+        replica.apply_change(RbacChange::UnassignRole {
+            user: 14,
+            role: 110
+        }),
+        Ok(false)
+    );
+    assert_eq!(
+        replica.apply_change(RbacChange::RemovePermission {
+            role: 112,
+            permission: 210
+        }),
+        Ok(false)
+    );
+    assert_eq!(replica.fingerprint(), source.fingerprint());
+}
+
+#[test]
+fn role_similarity() {
+    let (memory, users, _, _) = test_environment();
+
+    // sam and legolas share salesperson out of two roles
+    assert_eq!(memory.role_similarity(&users[2], &users[3]), Ok(0.5));
+
+    // The users share no role
+    assert_eq!(memory.role_similarity(&users[0], &users[2]), Ok(0.0));
+
+    // One user has no role
+    assert_eq!(memory.role_similarity(&users[4], &users[2]), Ok(0.0));
+
+    // Both users have no role
+    assert_eq!(
+        memory.role_similarity(&users[4], &MyUser { id: 15 }),
+        Ok(1.0)
+    );
+}
+
+#[test]
+fn require_min_roles_for_permission() {
+    let (mut memory, users, roles, permissions) = test_environment();
+
+    // Both legolas and sam get generate_form through salesperson
+    memory.require_min_roles_for_permission(&permissions[2], 2);
+
+    // legolas only has one role
+    assert_eq!(
+        memory.user_has_permission(&users[3], &permissions[2]),
+        Ok(false)
+    );
+    assert!(memory
+        .capabilities_response(&users[3])
+        .unwrap()
+        .permissions
+        .is_empty());
+
+    // sam has two roles
+    assert_eq!(
+        memory.user_has_permission(&users[2], &permissions[2]),
+        Ok(true)
+    );
+
+    // Permissions without the requirement are unaffected
+    assert_eq!(
+        memory.user_has_permission(&users[0], &permissions[0]),
+        Ok(true)
+    );
+
+    // A minimal model keeps enough roles to reproduce the decision
+    let minimal = memory.minimal_model_for(&users[2], &permissions[2]);
+    assert_eq!(
+        minimal.user_has_permission(&users[2], &permissions[2]),
+        Ok(true)
+    );
+
+    // Assigning a second role makes the permission effective
+    memory.assign_role(&users[3], &roles[0]).unwrap();
+    assert_eq!(
+        memory.user_has_permission(&users[3], &permissions[2]),
+        Ok(true)
+    );
+
+    // Removing the requirement
+    memory.require_min_roles_for_permission(&permissions[2], 1);
+    assert_eq!(
+        memory.user_has_permission(&users[1], &permissions[2]),
+        Ok(true)
+    );
+}
+
+#[cfg(feature = "openfga")]
+#[test]
+fn to_openfga_tuples() {
+    let (memory, _, _, _) = test_environment();
+
+    let tuples: HashSet<FgaTuple> = memory.to_openfga_tuples().into_iter().collect();
+
+    // 5 assignments and 12 grants
+    assert_eq!(tuples.len(), 17);
+    assert!(tuples.contains(&FgaTuple {
+        user: "user:12".to_owned(),
+        relation: "assignee".to_owned(),
+        object: "role:110".to_owned(),
+    }));
+    assert!(tuples.contains(&FgaTuple {
+        user: "role:110#assignee".to_owned(),
+        relation: "granted".to_owned(),
+        object: "permission:210".to_owned(),
+    }));
+    assert!(!tuples.contains(&FgaTuple {
+        user: "user:14".to_owned(),
+        relation: "assignee".to_owned(),
+        object: "role:110".to_owned(),
+    }));
+}
+
+#[cfg(feature = "openfga")]
+#[test]
+fn to_openfga_tuples_expiry() {
+    let (mut memory, users, roles, _) = test_environment();
+    memory
+        .assign_role_until(
+            &users[4],
+            &roles[0],
+            SystemTime::now() - Duration::from_secs(60),
+        )
+        .unwrap();
+
+    // The expired assignment isn't exported
+    let tuples: HashSet<FgaTuple> = memory.to_openfga_tuples().into_iter().collect();
+    assert_eq!(tuples.len(), 17);
+}
+
+#[test]
+fn emergency_override() {
+    let (mut memory, users, roles, permissions) = test_environment();
+
+    // The user has no role
+    assert_eq!(
+        memory.user_has_permission(&users[4], &permissions[0]),
+        Ok(false)
+    );
+
+    // An active override grants every permission
+    memory.set_clock(mock_now);
+    let until = mock_now() + Duration::from_secs(60);
+    memory.emergency_override(&users[4], until);
+    assert_eq!(
+        memory.user_has_permission(&users[4], &permissions[0]),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.permission_source(&users[4], &permissions[0]),
+        Some(PermissionSource::EmergencyOverride { until })
+    );
+
+    // Permissions granted by a role still name the role
+    memory.emergency_override(&users[2], until);
+    assert_eq!(
+        memory.permission_source(&users[2], &permissions[0]),
+        Some(PermissionSource::Role(roles[0].get_rbac_id()))
+    );
+
+    // The override lasts until `until`
+    advance_clock(Duration::from_secs(59));
+    assert_eq!(
+        memory.user_has_permission(&users[4], &permissions[0]),
+        Ok(true)
+    );
+
+    // The override has expired
+    advance_clock(Duration::from_secs(1));
+    assert_eq!(
+        memory.user_has_permission(&users[4], &permissions[0]),
+        Ok(false)
+    );
+    assert_eq!(memory.permission_source(&users[4], &permissions[0]), None);
+}
+
+#[test]
+fn deny_permission() {
+    let (mut memory, users, _, permissions) = test_environment();
+
+    // The denial takes precedence over the grant of supervisor
+    assert!(memory.deny_permission(&users[1], &permissions[3]));
+    assert!(!memory.deny_permission(&users[1], &permissions[3]));
+    assert_eq!(
+        memory.user_has_permission(&users[1], &permissions[3]),
+        Ok(false)
+    );
+    assert_eq!(memory.permission_source(&users[1], &permissions[3]), None);
+    assert!(!memory
+        .iter_user_permission_ids(&users[1])
+        .unwrap()
+        .any(|p| p == permissions[3].get_rbac_id()));
+
+    // Other permissions and users are unaffected
+    assert_eq!(
+        memory.user_has_permission(&users[1], &permissions[2]),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.user_has_permission(&users[0], &permissions[3]),
+        Ok(true)
+    );
+
+    // A denied prerequisite makes dependent permissions ineffective
+    memory
+        .set_permission_prerequisite(&permissions[0], &permissions[3])
+        .unwrap();
+    assert_eq!(
+        memory.user_has_permission(&users[1], &permissions[0]),
+        Ok(false)
+    );
+
+    // The denial is lifted
+    assert!(memory.allow_permission(&users[1], &permissions[3]));
+    assert!(!memory.allow_permission(&users[1], &permissions[3]));
+    assert_eq!(
+        memory.user_has_permission(&users[1], &permissions[3]),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.user_has_permission(&users[1], &permissions[0]),
+        Ok(true)
+    );
+}
+
+#[test]
+fn user_has_permission_glob() {
+    let mut memory: InMemoryRbac<String, String, String> = InMemoryRbac::new();
+    let user = "sam".to_string();
+    let admin = "gandalf".to_string();
+    let role = "accountant".to_string();
+    let admin_role = "administrator".to_string();
+    memory.assign_role(&user, &role).unwrap();
+    memory.assign_role(&admin, &admin_role).unwrap();
+    memory
+        .add_permission(&role, &"billing.*".to_string())
+        .unwrap();
+    memory
+        .add_permission(&role, &"reports.read".to_string())
+        .unwrap();
+    memory
+        .add_permission(&admin_role, &"*".to_string())
+        .unwrap();
+
+    // A prefix matches all sub-permissions
+    assert_eq!(
+        memory.user_has_permission_glob(&user, &"billing.invoice.read".to_string()),
+        Ok(true)
+    );
+
+    // Exact matches still work
+    assert_eq!(
+        memory.user_has_permission_glob(&user, &"reports.read".to_string()),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.user_has_permission_glob(&user, &"billing.*".to_string()),
+        Ok(true)
+    );
+
+    // No permission matches
+    assert_eq!(
+        memory.user_has_permission_glob(&user, &"billing".to_string()),
+        Ok(false)
+    );
+    assert_eq!(
+        memory.user_has_permission_glob(&user, &"reports.write".to_string()),
+        Ok(false)
+    );
+
+    // A bare `*` matches everything
+    assert_eq!(
+        memory.user_has_permission_glob(&admin, &"reports.write".to_string()),
+        Ok(true)
+    );
+
+    // The plain check doesn't match prefixes
+    assert_eq!(
+        memory.user_has_permission(&user, &"billing.invoice.read".to_string()),
+        Ok(false)
+    );
+
+    // A prefix match needs the prerequisites of the permission
+    memory
+        .set_permission_prerequisite(
+            &"billing.invoice.read".to_string(),
+            &"reports.write".to_string(),
+        )
+        .unwrap();
+    assert_eq!(
+        memory.user_has_permission_glob(&user, &"billing.invoice.read".to_string()),
+        Ok(false)
+    );
+    assert_eq!(
+        memory.user_has_permission_glob(&admin, &"billing.invoice.read".to_string()),
+        Ok(true)
+    );
+    memory
+        .set_permission_prerequisite(
+            &"billing.invoice.read".to_string(),
+            &"reports.read".to_string(),
+        )
+        .unwrap();
+    assert_eq!(
+        memory.user_has_permission_glob(&user, &"billing.invoice.read".to_string()),
+        Ok(true)
+    );
+
+    // A prefix match needs the minimum number of roles of the permission
+    memory.require_min_roles_for_permission(&"billing.invoice.read".to_string(), 2);
+    assert_eq!(
+        memory.user_has_permission_glob(&user, &"billing.invoice.read".to_string()),
+        Ok(false)
+    );
+}
+
+#[test]
+fn which_role_grants() {
+    let (mut memory, users, roles, permissions) = test_environment();
+
+    // Only one role grants the permission
+    assert_eq!(
+        memory.which_role_grants(&users[2], &permissions[2]),
+        Ok(Some(roles[1].get_rbac_id()))
+    );
+
+    // Both roles of the user grant the permission
+    memory.add_permission(&roles[1], &permissions[0]).unwrap();
+    let granted = memory
+        .which_role_grants(&users[2], &permissions[0])
+        .unwrap();
+    assert!(granted == Some(roles[0].get_rbac_id()) || granted == Some(roles[1].get_rbac_id()));
+    let mut granting: Vec<u32> = memory
+        .iter_roles_granting(&users[2], &permissions[0])
+        .collect();
+    granting.sort_unstable();
+    assert_eq!(
+        granting,
+        vec![roles[0].get_rbac_id(), roles[1].get_rbac_id()]
+    );
+
+    // The user doesn't have the permission
+    assert_eq!(
+        memory.which_role_grants(&users[2], &permissions[3]),
+        Ok(None)
+    );
+    assert_eq!(
+        memory.which_role_grants(&users[4], &permissions[0]),
+        Ok(None)
+    );
+    assert_eq!(
+        memory
+            .iter_roles_granting(&users[4], &permissions[0])
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn assign_role_until() {
+    let (mut memory, users, roles, permissions) = test_environment();
+    let past = SystemTime::now() - Duration::from_secs(60);
+    let future = SystemTime::now() + Duration::from_secs(60);
+
+    // The assignment hasn't expired yet
+    assert_eq!(
+        memory.assign_role_until(&users[4], &roles[0], future),
+        Ok(true)
+    );
+    assert_eq!(memory.user_has_role(&users[4], &roles[0]), Ok(true));
+    assert_eq!(
+        memory.user_has_permission(&users[4], &permissions[0]),
+        Ok(true)
+    );
+
+    // The assignment has expired
+    assert_eq!(
+        memory.assign_role_until(&users[4], &roles[0], past),
+        Ok(false)
+    );
+    assert_eq!(memory.user_has_role(&users[4], &roles[0]), Ok(false));
+    assert_eq!(
+        memory.user_has_permission(&users[4], &permissions[0]),
+        Ok(false)
+    );
+    assert_eq!(
+        memory.iter_user_role_ids(&users[4]).unwrap_err(),
+        InMemoryRbacError::UserHasNoRoles
+    );
+    assert_eq!(memory.iter_users_with_role(&roles[0]).count(), 1);
+
+    // Other roles of the user are unaffected
+    memory
+        .assign_role_until(&users[2], &roles[1], past)
+        .unwrap();
+    assert_eq!(memory.iter_user_role_ids(&users[2]).unwrap().count(), 1);
+    assert_eq!(
+        memory.user_has_permission(&users[2], &permissions[0]),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.user_has_permission(&users[2], &permissions[2]),
+        Ok(false)
+    );
+
+    // Expired assignments are removed
+    assert_eq!(memory.total_assignments(), 6);
+    assert_eq!(memory.purge_expired_roles(), 2);
+    assert_eq!(memory.total_assignments(), 4);
+    assert_eq!(memory.user_has_role(&users[2], &roles[0]), Ok(true));
+
+    // Assigning the role again without an expiry makes it permanent
+    memory
+        .assign_role_until(&users[4], &roles[0], past)
+        .unwrap();
+    assert_eq!(memory.assign_role(&users[4], &roles[0]), Ok(true));
+    assert_eq!(memory.user_has_role(&users[4], &roles[0]), Ok(true));
+    assert_eq!(memory.purge_expired_roles(), 0);
+}
+
+#[test]
+fn expired_roles_are_hidden() {
+    let (mut memory, users, roles, _) = test_environment();
+    let past = SystemTime::now() - Duration::from_secs(60);
+    memory
+        .assign_role_until(&users[4], &roles[0], past)
+        .unwrap();
+    memory
+        .assign_role_until(&users[2], &roles[1], past)
+        .unwrap();
+
+    // The roles of a user
+    let response = memory.capabilities_response(&users[4]).unwrap();
+    assert!(response.roles.is_empty());
+    assert!(response.permissions.is_empty());
+    assert_eq!(
+        memory.capabilities_response(&users[2]).unwrap().roles,
+        vec![110]
+    );
+    assert_eq!(
+        memory.recommend_roles(&users[4]),
+        Err(InMemoryRbacError::UserHasNoRoles)
+    );
+    assert_eq!(
+        memory.iter_user_permission_ids(&users[4]).err(),
+        Some(InMemoryRbacError::UserHasNoRoles)
+    );
+    assert_eq!(
+        memory.resolve_permissions_bounded(&users[4], 10),
+        Err(InMemoryRbacError::UserHasNoRoles)
+    );
+
+    // The users of the model
+    assert!(!memory.iter_users().any(|user| user == 14));
+    assert!(!memory.iter_users_where(|&user| user == 14).any(|_| true));
+    let fanout: HashMap<u32, usize> = memory.role_fanout().into_iter().collect();
+    assert_eq!(fanout[&110], 1);
+    assert_eq!(fanout[&111], 1);
+
+    // Constraints only consider roles that haven't expired
+    memory.set_max_users_per_role(&roles[1], 2);
+    assert_eq!(memory.can_assign(&users[2], &roles[1]), Ok(()));
+    memory.add_mutually_exclusive_roles(&roles[1], &roles[2]);
+    assert_eq!(memory.can_assign(&users[2], &roles[2]), Ok(()));
+}
+
+#[test]
+fn assign_role_until_expires() {
+    let (mut memory, users, roles, permissions) = test_environment();
+    memory.set_clock(mock_now);
+
+    let until = mock_now() + Duration::from_secs(60);
+    memory
+        .assign_role_until(&users[4], &roles[2], until)
+        .unwrap();
+    advance_clock(Duration::from_secs(59));
+    assert_eq!(memory.user_has_role(&users[4], &roles[2]), Ok(true));
+    assert_eq!(
+        memory.user_has_permission(&users[4], &permissions[3]),
+        Ok(true)
+    );
+
+    // The assignment has expired
+    advance_clock(Duration::from_secs(1));
+    assert_eq!(memory.user_has_role(&users[4], &roles[2]), Ok(false));
+    assert_eq!(
+        memory.user_has_permission(&users[4], &permissions[3]),
+        Ok(false)
+    );
+    assert_eq!(memory.purge_expired_roles(), 1);
+}
+
+#[cfg(feature = "phf")]
+#[test]
+fn into_phf_expiry() {
+    let (mut memory, users, roles, permissions) = test_environment();
+    memory.set_clock(mock_now);
+    let until = mock_now() + Duration::from_secs(60);
+    memory
+        .assign_role_until(&users[2], &roles[2], until)
+        .unwrap();
+    memory
+        .assign_role_until(&users[4], &roles[0], until)
+        .unwrap();
+    let phf = memory.into_phf();
+    assert!(phf.user_has_permission(&users[2], &permissions[3]));
+    assert!(phf.user_has_permission(&users[4], &permissions[0]));
+    assert!(phf.user_has_role(&users[4], &roles[0]));
+
+    // The assignments have expired, but the other roles of sam remain
+    advance_clock(Duration::from_secs(60));
+    assert!(!phf.user_has_permission(&users[2], &permissions[3]));
+    assert!(phf.user_has_permission(&users[2], &permissions[0]));
+    assert!(!phf.user_has_permission(&users[4], &permissions[0]));
+    assert!(!phf.user_has_role(&users[4], &roles[0]));
+}
+
+#[cfg(feature = "phf")]
+#[test]
+fn into_phf() {
+    let (memory, users, roles, permissions) = test_environment();
+    let mut expected = Vec::new();
+    for user in &users {
+        for permission in &permissions {
+            expected.push(memory.user_has_permission(user, permission) == Ok(true));
+        }
+    }
+
+    // The checks match the source model
+    let phf = memory.into_phf();
+    let mut actual = Vec::new();
+    for user in &users {
+        for permission in &permissions {
+            actual.push(phf.user_has_permission(user, permission));
+        }
+    }
+    assert_eq!(actual, expected);
+    assert!(phf.user_has_role(&users[2], &roles[1]));
+    assert!(!phf.user_has_role(&users[2], &roles[2]));
+
+    // The user has no role
+    assert!(!phf.user_has_role(&users[4], &roles[0]));
+    assert_eq!(phf.len(), 4);
+
+    // A large model
+    let mut memory: InMemoryRbac<MyUser, MyRole, MyPermission> = InMemoryRbac::new();
+    let users: Vec<_> = (0..5000).map(|id| MyUser { id }).collect();
+    let roles: Vec<_> = (0..40).map(|id| MyRole { id }).collect();
+    let permissions: Vec<_> = (0..100).map(|id| MyPermission { id }).collect();
+    for user in &users {
+        memory
+            .assign_role(user, &roles[(user.id * 7 % 40) as usize])
+            .unwrap();
+        memory
+            .assign_role(user, &roles[(user.id * 13 % 40) as usize])
+            .unwrap();
+    }
+    for role in &roles {
+        for step in 0..5 {
+            let permission = &permissions[((role.id * 11 + step * 17) % 100) as usize];
+            memory.add_permission(role, permission).unwrap();
+        }
+    }
+    let checks: Vec<(usize, usize)> = (0..6000)
+        .map(|i| (i % 5000 * 31 % 5000, i * 37 % 100))
+        .collect();
+    let expected: Vec<bool> = checks
+        .iter()
+        .map(|&(u, p)| memory.user_has_permission(&users[u], &permissions[p]) == Ok(true))
+        .collect();
+    let phf = memory.into_phf();
+    let actual: Vec<bool> = checks
+        .iter()
+        .map(|&(u, p)| phf.user_has_permission(&users[u], &permissions[p]))
+        .collect();
+    assert_eq!(actual, expected);
+    assert!(actual.contains(&true) && actual.contains(&false));
+    assert!(!phf.user_has_permission(&MyUser { id: 5000 }, &permissions[0]));
+}
+
+#[test]
+fn permission_meta() {
+    let (mut memory, users, _, permissions) = test_environment();
+    let meta = PermissionMeta {
+        description: "Alter the state of a customer".to_owned(),
+        risk_level: RiskLevel::High,
+    };
+
+    // The permission has no metadata
+    assert_eq!(memory.get_permission_meta(&permissions[3]), None);
+
+    // Set metadata
+    assert_eq!(
+        memory.set_permission_meta(&permissions[3], meta.clone()),
+        None
+    );
+    assert_eq!(memory.get_permission_meta(&permissions[3]), Some(&meta));
+    assert_eq!(memory.get_permission_meta(&permissions[2]), None);
+
+    // Replace metadata
+    let low = PermissionMeta {
+        risk_level: RiskLevel::Low,
+        ..meta.clone()
+    };
+    assert_eq!(
+        memory.set_permission_meta(&permissions[3], low.clone()),
+        Some(meta)
+    );
+    assert_eq!(memory.get_permission_meta(&permissions[3]), Some(&low));
+
+    // Checks are unaffected
+    assert_eq!(
+        memory.user_has_permission(&users[1], &permissions[3]),
+        Ok(true)
+    );
+}
+
+#[test]
+fn can_assign() {
+    let (mut memory, users, roles, _) = test_environment();
+    memory.add_mutually_exclusive_roles(&roles[0], &roles[2]);
+
+    // The user holds a mutually exclusive role
+    assert_eq!(
+        memory.can_assign(&users[2], &roles[2]),
+        Err(ConstraintViolation::MutuallyExclusiveRole)
+    );
+    assert_eq!(
+        memory.can_assign(&users[1], &roles[0]),
+        Err(ConstraintViolation::MutuallyExclusiveRole)
+    );
+    assert_eq!(
+        memory.assign_role(&users[2], &roles[2]),
+        Err(InMemoryRbacError::ConstraintViolated(
+            ConstraintViolation::MutuallyExclusiveRole
+        ))
+    );
+    assert_eq!(memory.user_has_role(&users[2], &roles[2]), Ok(false));
+
+    // No constraint is violated
+    assert_eq!(memory.can_assign(&users[3], &roles[2]), Ok(()));
+    assert_eq!(memory.can_assign(&users[4], &roles[0]), Ok(()));
+
+    // The role is held by too many users
+    memory.set_max_users_per_role(&roles[1], 2);
+    assert_eq!(
+        memory.can_assign(&users[4], &roles[1]),
+        Err(ConstraintViolation::MaxUsersPerRoleExceeded)
+    );
+
+    // The user already has the role
+    assert_eq!(memory.can_assign(&users[3], &roles[1]), Ok(()));
+
+    // The role is unknown
+    let temp_role = MyRole { id: 116 };
+    assert_eq!(memory.can_assign(&users[4], &temp_role), Ok(()));
+    memory.set_strict_roles(true);
+    assert_eq!(
+        memory.can_assign(&users[4], &temp_role),
+        Err(ConstraintViolation::UnknownRole)
+    );
+}
+
+#[cfg(feature = "csv")]
+#[test]
+fn from_csv() {
+    let assignments = "user,role\nsam,agent\nlegolas, salesperson\n";
+    let grants = "role,permission\nagent,make_calls\nsalesperson,generate_form\n";
+
+    // Import a couple of rows
+    let memory = InMemoryRbac::from_csv(assignments.as_bytes(), grants.as_bytes()).unwrap();
+    let sam = "sam".to_owned();
+    assert_eq!(
+        memory.user_has_permission(&sam, &"make_calls".to_owned()),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.user_has_permission(&sam, &"generate_form".to_owned()),
+        Ok(false)
+    );
+    assert_eq!(
+        memory.user_has_permission(&"legolas".to_owned(), &"generate_form".to_owned()),
+        Ok(true)
+    );
+
+    // A row has too many columns
+    let grants = "role,permission\nagent,make_calls\nagent,generate_form,alter_state\n";
+    let err = InMemoryRbac::from_csv(assignments.as_bytes(), grants.as_bytes())
+        .err()
+        .unwrap();
+    assert_eq!(err.input, CsvInput::Grants);
+    assert_eq!(err.row, 2);
+}
+
+#[test]
+fn export_matrix() {
+    let (memory, _, _, _) = test_environment();
+
+    let rows = memory.export_matrix();
+    assert_eq!(rows.len(), 13);
+    assert!(rows.contains(&(12, 111, 212)));
+    assert!(!rows.contains(&(12, 111, 210)));
+    assert_eq!(rows.iter().filter(|(user, _, _)| *user == 12).count(), 3);
+}
+
+#[cfg(feature = "csv")]
+#[test]
+fn to_csv() {
+    let (memory, _, _, _) = test_environment();
+
+    let mut out = Vec::new();
+    memory.to_csv(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let mut lines = out.lines();
+    assert_eq!(lines.next(), Some("user,role,permission"));
+    assert_eq!(lines.clone().count(), 13);
+    assert!(lines.any(|line| line == "12,111,212"));
+}
+
+#[test]
+fn access_subset_of() {
+    let (memory, users, _, _) = test_environment();
+
+    // The second user has every permission of the first one
+    assert_eq!(memory.access_subset_of(&users[3], &users[0]), Ok(true));
+    assert_eq!(memory.access_subset_of(&users[2], &users[1]), Ok(true));
+
+    // The second user lacks some permissions
+    assert_eq!(memory.access_subset_of(&users[0], &users[3]), Ok(false));
+    assert_eq!(memory.access_subset_of(&users[2], &users[3]), Ok(false));
+
+    // The first user has no permissions
+    assert_eq!(memory.access_subset_of(&users[4], &users[3]), Ok(true));
+    assert_eq!(memory.access_subset_of(&users[3], &users[4]), Ok(false));
+}
+
+#[test]
+fn permission_difference() {
+    let (memory, users, _, permissions) = test_environment();
+
+    // elrond can alter the state, sam can't
+    assert_eq!(
+        memory.permission_difference(&users[1], &users[2]),
+        Ok(HashSet::from([permissions[3].id]))
+    );
+    assert_eq!(
+        memory.permission_difference(&users[2], &users[1]),
+        Ok(HashSet::new())
+    );
+
+    // A user without roles has no permissions
+    assert_eq!(
+        memory.permission_difference(&users[4], &users[2]),
+        Ok(HashSet::new())
+    );
+    assert_eq!(
+        memory.permission_difference(&users[2], &users[4]),
+        Ok(HashSet::from([210, 211, 212]))
+    );
+}
+
+#[test]
+fn resolve_permissions_bounded() {
+    let (mut memory, users, roles, _) = test_environment();
+    for id in 0..20 {
+        let parent = MyRole { id: 120 + id };
+        memory.add_role_parent(&roles[0], &parent).unwrap();
+        memory
+            .add_permission(&parent, &MyPermission { id: 300 + id })
+            .unwrap();
+    }
+    let all: HashSet<u32> = memory
+        .iter_user_permission_ids(&users[2])
+        .unwrap()
+        .collect();
+    assert_eq!(all.len(), 23);
+
+    // A budget for all 22 roles and 23 permissions resolves everything
+    assert_eq!(
+        memory.resolve_permissions_bounded(&users[2], 45),
+        Ok((all.clone(), false))
+    );
+
+    // A smaller budget returns a part of the permissions
+    let (partial, truncated) = memory.resolve_permissions_bounded(&users[2], 44).unwrap();
+    assert!(truncated);
+    assert_eq!(partial.len(), 22);
+    let (partial, truncated) = memory.resolve_permissions_bounded(&users[2], 10).unwrap();
+    assert!(truncated);
+    assert!(partial.len() < 10);
+    assert!(partial.is_subset(&all));
+
+    // The user has no roles
+    assert_eq!(
+        memory.resolve_permissions_bounded(&users[4], 45),
+        Err(InMemoryRbacError::UserHasNoRoles)
+    );
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn versioned_json() {
+    let (mut memory, users, roles, permissions) = test_environment();
+    memory
+        .set_permission_prerequisite(&permissions[3], &permissions[0])
+        .unwrap();
+    memory.add_role_parent(&roles[1], &roles[0]).unwrap();
+
+    // A v1 blob round-trips
+    let json = memory.to_versioned_json().unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["version"], 1);
+    let restored: InMemoryRbac<MyUser, MyRole, MyPermission> =
+        InMemoryRbac::from_versioned_json(&json).unwrap();
+    assert_eq!(restored.fingerprint(), memory.fingerprint());
+    for user in &users {
+        for permission in &permissions {
+            assert_eq!(
+                restored.user_has_permission(user, permission),
+                memory.user_has_permission(user, permission)
+            );
+        }
+    }
+
+    // A future version is rejected
+    let future = r#"{"version":2,"data":{"assignments":{},"grants":{}}}"#;
+    let result: Result<InMemoryRbac<MyUser, MyRole, MyPermission>, _> =
+        InMemoryRbac::from_versioned_json(future);
+    match result {
+        Err(VersionedJsonError::UnsupportedVersion(2)) => {}
+        _ => panic!("expected an unsupported version"),
+    }
+
+    // The migration hook converts older data
+    let old = r#"{"version":0,"data":{"users":{"14":[110]},"grants":{"110":[210]}}}"#;
+    let migrated: InMemoryRbac<MyUser, MyRole, MyPermission> =
+        InMemoryRbac::from_versioned_json_with(old, |version, mut data| {
+            assert_eq!(version, 0);
+            data["assignments"] = data["users"].take();
+            Ok(data)
+        })
+        .unwrap();
+    assert_eq!(
+        migrated.user_has_permission(&users[4], &permissions[0]),
+        Ok(true)
+    );
+
+    // The data doesn't match the version
+    let invalid = r#"{"version":1,"data":{"grants":{}}}"#;
+    let result: Result<InMemoryRbac<MyUser, MyRole, MyPermission>, _> =
+        InMemoryRbac::from_versioned_json(invalid);
+    match result {
+        Err(VersionedJsonError::Json(_)) => {}
+        _ => panic!("expected a JSON error"),
+    }
+}
+
+#[test]
+fn role_prerequisite() {
+    let (mut memory, users, roles, _) = test_environment();
+    memory.set_role_prerequisite(&roles[2], &roles[0]);
+
+    // The user lacks the prerequisite role
+    assert_eq!(
+        memory.assign_role(&users[3], &roles[2]),
+        Err(InMemoryRbacError::MissingPrerequisiteRole)
+    );
+    assert_eq!(
+        memory.can_assign(&users[4], &roles[2]),
+        Err(ConstraintViolation::MissingPrerequisiteRole)
+    );
+    assert_eq!(memory.user_has_role(&users[3], &roles[2]), Ok(false));
+
+    // The user holds the prerequisite role
+    assert_eq!(memory.assign_role(&users[3], &roles[0]), Ok(true));
+    assert_eq!(memory.assign_role(&users[3], &roles[2]), Ok(true));
+
+    // The user already has the role
+    assert_eq!(memory.assign_role(&users[1], &roles[2]), Ok(false));
+
+    // The prerequisite is removed
+    assert!(memory.remove_role_prerequisite(&roles[2], &roles[0]));
+    assert!(!memory.remove_role_prerequisite(&roles[2], &roles[0]));
+    assert_eq!(memory.assign_role(&users[4], &roles[2]), Ok(true));
+}
+
+#[test]
+fn iter_users_and_roles() {
+    let (memory, _, _, _) = test_environment();
+
+    // frodo has no roles
+    let users: HashSet<u32> = memory.iter_users().collect();
+    assert_eq!(users, [10, 11, 12, 13].iter().copied().collect());
+
+    let roles: HashSet<u32> = memory.iter_roles().collect();
+    assert_eq!(roles, [110, 111, 112, 113].iter().copied().collect());
+}
+
+#[test]
+fn iter_users_where() {
+    let (memory, _, _, _) = test_environment();
+
+    // Users with an even id
+    let even: HashSet<u32> = memory.iter_users_where(|id| id % 2 == 0).collect();
+    assert_eq!(even, vec![10, 12].into_iter().collect());
+
+    // Users with an odd id
+    let odd: HashSet<u32> = memory.iter_users_where(|id| id % 2 == 1).collect();
+    assert_eq!(odd, vec![11, 13].into_iter().collect());
+
+    // No user matches
+    assert_eq!(memory.iter_users_where(|&id| id > 100).count(), 0);
+}
+
+#[test]
+fn iter_users_with_role() {
+    let (memory, users, roles, _) = test_environment();
+
+    // Multiple users have the role
+    let salespeople: HashSet<u32> = memory.iter_users_with_role(&roles[1]).collect();
+    assert_eq!(
+        salespeople,
+        vec![users[2].get_rbac_id(), users[3].get_rbac_id()]
+            .into_iter()
+            .collect()
+    );
+
+    // A single user has the role
+    let admins: Vec<u32> = memory.iter_users_with_role(&roles[3]).collect();
+    assert_eq!(admins, vec![users[0].get_rbac_id()]);
+
+    // No user has the role
+    assert_eq!(memory.iter_users_with_role(&MyRole { id: 116 }).count(), 0);
+}
+
+#[test]
+fn recommend_roles() {
+    let (memory, users, roles, _) = test_environment();
+
+    // Roles covered by the permissions of the user are redundant and come first
+    let recommended = memory.recommend_roles(&users[1]).unwrap();
+    assert_eq!(recommended.len(), 3);
+    let redundant: HashSet<u32> = recommended[..2].iter().cloned().collect();
+    assert_eq!(
+        redundant,
+        vec![roles[0].get_rbac_id(), roles[1].get_rbac_id()]
+            .into_iter()
+            .collect()
+    );
+    assert_eq!(recommended[2], roles[3].get_rbac_id());
+
+    // No role is mostly covered
+    assert_eq!(memory.recommend_roles(&users[3]), Ok(vec![]));
+
+    // The user has no role
+    assert_eq!(
+        memory.recommend_roles(&users[4]),
+        Err(InMemoryRbacError::UserHasNoRoles)
+    );
+}
+
+#[test]
+fn iter_roles_with_permission() {
+    let (memory, _, roles, permissions) = test_environment();
+
+    // Multiple roles have the permission
+    let granting: HashSet<u32> = memory.iter_roles_with_permission(&permissions[0]).collect();
+    assert_eq!(
+        granting,
+        vec![
+            roles[0].get_rbac_id(),
+            roles[2].get_rbac_id(),
+            roles[3].get_rbac_id()
+        ]
+        .into_iter()
+        .collect()
+    );
+
+    // A single role has the permission
+    let granting: Vec<u32> = memory.iter_roles_with_permission(&permissions[4]).collect();
+    assert_eq!(granting, vec![roles[3].get_rbac_id()]);
+
+    // The permission is unknown
+    assert_eq!(
+        memory
+            .iter_roles_with_permission(&MyPermission { id: 215 })
+            .count(),
+        0
+    );
+
+    // Roles without own permissions inherit them from their parents, and each role is yielded once
+    let mut memory = memory;
+    let inheriting = MyRole { id: 114 };
+    memory.add_role_parent(&inheriting, &roles[3]).unwrap();
+    memory.add_role_parent(&roles[1], &roles[3]).unwrap();
+    let mut granting: Vec<u32> = memory.iter_roles_with_permission(&permissions[4]).collect();
+    granting.sort_unstable();
+    assert_eq!(
+        granting,
+        vec![roles[1].get_rbac_id(), roles[3].get_rbac_id(), 114]
+    );
+}
+
+#[test]
+fn access_equivalent() {
+    let (memory, users, roles, permissions) = test_environment();
+    let (mut other, _, _, _) = test_environment();
+
+    // Identical models
+    assert_eq!(
+        memory.access_equivalent(&other, &users, &permissions),
+        Ok(true)
+    );
+
+    // A role without permissions doesn't change access
+    let empty_role = MyRole { id: 116 };
+    other.assign_role(&users[4], &empty_role).unwrap();
+    assert_ne!(memory.fingerprint(), other.fingerprint());
+    assert_eq!(
+        memory.access_equivalent(&other, &users, &permissions),
+        Ok(true)
+    );
+
+    // A permission changes access
+    other.add_permission(&roles[1], &permissions[3]).unwrap();
+    assert_eq!(
+        memory.access_equivalent(&other, &users, &permissions),
+        Ok(false)
+    );
+
+    // Only the given permissions are compared
+    assert_eq!(
+        memory.access_equivalent(&other, &users, &permissions[..3]),
+        Ok(true)
+    );
+}
+
+#[test]
+fn role_fanout() {
+    let (memory, _, roles, _) = test_environment();
+
+    let fanout = memory.role_fanout();
+    assert_eq!(fanout.len(), 4);
+    assert!(fanout.windows(2).all(|w| w[0].1 >= w[1].1));
+
+    // salesperson is held by sam and legolas
+    assert_eq!(fanout[0], (roles[1].get_rbac_id(), 2));
+
+    // administrator is only held by gandalf
+    assert!(fanout.contains(&(roles[3].get_rbac_id(), 1)));
+}
+
+#[test]
+fn iter_user_permission_ids() {
+    let (memory, users, _, permissions) = test_environment();
+
+    // The permissions of multiple roles are unioned
+    let sam: Vec<u32> = memory
+        .iter_user_permission_ids(&users[2])
+        .unwrap()
+        .collect();
+    assert_eq!(sam.len(), 3);
+    assert_eq!(
+        sam.into_iter().collect::<HashSet<u32>>(),
+        permissions[..3].iter().map(|p| p.get_rbac_id()).collect()
+    );
+
+    // Permissions granted by a single role
+    let legolas: HashSet<u32> = memory
+        .iter_user_permission_ids(&users[3])
+        .unwrap()
+        .collect();
+    assert_eq!(
+        legolas,
+        vec![permissions[2].get_rbac_id()].into_iter().collect()
+    );
+
+    // The user has no role
+    assert!(matches!(
+        memory.iter_user_permission_ids(&users[4]),
+        Err(InMemoryRbacError::UserHasNoRoles)
+    ));
+}
+
+#[test]
+fn role_hierarchy() {
+    let (mut memory, users, roles, permissions) = test_environment();
+    let team_lead = MyRole { id: 115 };
+    memory.add_permission(&team_lead, &permissions[4]).unwrap();
+
+    // legolas is a salesperson, who inherits from agent
+    assert_eq!(memory.add_role_parent(&roles[1], &roles[0]), Ok(true));
+    assert_eq!(memory.add_role_parent(&roles[1], &roles[0]), Ok(false));
+    assert_eq!(
+        memory.user_has_permission(&users[3], &permissions[0]),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.role_has_permission(&roles[1], &permissions[1]),
+        Ok(true)
+    );
+
+    // Two levels: agent inherits from team_lead
+    assert_eq!(memory.add_role_parent(&roles[0], &team_lead), Ok(true));
+    assert_eq!(
+        memory.user_has_permission(&users[3], &permissions[4]),
+        Ok(true)
+    );
+    let inherited: HashSet<u32> = memory
+        .iter_role_permission_ids(&roles[1])
+        .unwrap()
+        .collect();
+    assert_eq!(inherited, vec![210, 211, 212, 214].into_iter().collect());
+    assert_eq!(
+        memory.permission_source(&users[3], &permissions[4]),
+        Some(PermissionSource::Inherited {
+            role: roles[1].get_rbac_id(),
+            chain: vec![roles[0].get_rbac_id(), team_lead.get_rbac_id()]
+        })
+    );
+
+    // A cycle is rejected
+    assert_eq!(
+        memory.add_role_parent(&team_lead, &roles[1]),
+        Err(InMemoryRbacError::RoleHierarchyCycle)
+    );
+    assert_eq!(
+        memory.add_role_parent(&roles[0], &roles[0]),
+        Err(InMemoryRbacError::RoleHierarchyCycle)
+    );
+
+    // Removing a parent removes the inherited permissions
+    assert!(memory.remove_role_parent(&roles[1], &roles[0]));
+    assert!(!memory.remove_role_parent(&roles[1], &roles[0]));
+    assert_eq!(
+        memory.user_has_permission(&users[3], &permissions[0]),
+        Ok(false)
+    );
+    assert_eq!(
+        memory.user_has_permission(&users[2], &permissions[4]),
+        Ok(true)
+    );
+}
+
+#[test]
+fn roles_within() {
+    let (mut memory, _, roles, _) = test_environment();
+    let team_lead = MyRole { id: 115 };
+    memory.add_role_parent(&roles[1], &roles[0]).unwrap();
+    memory.add_role_parent(&roles[0], &team_lead).unwrap();
+
+    // A single hop only reaches the direct parent
+    assert_eq!(
+        memory.roles_within(&roles[1], 1),
+        vec![roles[0].get_rbac_id()]
+    );
+
+    // More hops reach the whole chain
+    let within: HashSet<u32> = memory.roles_within(&roles[1], 5).into_iter().collect();
+    assert_eq!(
+        within,
+        vec![roles[0].get_rbac_id(), team_lead.get_rbac_id()]
+            .into_iter()
+            .collect()
+    );
+
+    // No hops and roles without parents reach nothing
+    assert!(memory.roles_within(&roles[1], 0).is_empty());
+    assert!(memory.roles_within(&team_lead, 2).is_empty());
+}
+
+#[test]
+fn role_own_permissions() {
+    let (mut memory, _, roles, permissions) = test_environment();
+    memory.add_role_parent(&roles[1], &roles[0]).unwrap();
+
+    // salesperson inherits two permissions from agent but only owns one
+    assert_eq!(
+        memory.iter_role_permission_ids(&roles[1]).unwrap().count(),
+        3
+    );
+    assert_eq!(
+        memory.role_own_permissions(&roles[1]),
+        Ok(vec![permissions[2].get_rbac_id()])
+    );
+
+    // A role that only inherits permissions owns none
+    let trainee = MyRole { id: 115 };
+    memory.add_role_parent(&trainee, &roles[0]).unwrap();
+    assert_eq!(
+        memory.role_own_permissions(&trainee),
+        Err(InMemoryRbacError::RoleHasNoPermissions)
+    );
+}
+
+#[test]
+fn materialize_inheritance() {
+    let (mut memory, users, roles, permissions) = test_environment();
+    let team_lead = MyRole { id: 115 };
+    let head = MyRole { id: 116 };
+    let audit = MyPermission { id: 215 };
+    memory.add_permission(&head, &audit).unwrap();
+    memory.add_role_parent(&roles[1], &team_lead).unwrap();
+    memory.add_role_parent(&team_lead, &head).unwrap();
+    assert_eq!(memory.materialize_inheritance(), Ok(()));
+
+    // legolas is a salesperson and gets the permission of the grandparent role
+    assert_eq!(memory.role_has_permission(&roles[1], &audit), Ok(true));
+    assert_eq!(memory.user_has_permission(&users[3], &audit), Ok(true));
+    assert_eq!(
+        memory.user_has_permission(&users[3], &permissions[2]),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.user_has_permission(&users[3], &permissions[0]),
+        Ok(false)
+    );
+    let materialized: HashSet<u32> = memory
+        .iter_role_permission_ids(&roles[1])
+        .unwrap()
+        .collect();
+    assert_eq!(materialized, vec![212, 215].into_iter().collect());
+
+    // Changing the hierarchy discards the resolved permissions
+    memory.remove_role_parent(&team_lead, &head);
+    assert_eq!(memory.user_has_permission(&users[3], &audit), Ok(false));
+    memory.add_role_parent(&roles[1], &roles[0]).unwrap();
+    assert_eq!(memory.materialize_inheritance(), Ok(()));
+    assert_eq!(
+        memory.user_has_permission(&users[3], &permissions[0]),
+        Ok(true)
+    );
+}
+
+#[test]
+fn duplicate_access_groups() {
+    let (mut memory, users, roles, _) = test_environment();
+
+    // No two users have the same permissions
+    assert!(memory.duplicate_access_groups().is_empty());
+
+    // frodo gets the same role as legolas
+    memory.assign_role(&users[4], &roles[1]).unwrap();
+    let groups = memory.duplicate_access_groups();
+    assert_eq!(groups.len(), 1);
+    let group: HashSet<u32> = groups[0].iter().cloned().collect();
+    assert_eq!(
+        group,
+        vec![users[3].get_rbac_id(), users[4].get_rbac_id()]
+            .into_iter()
+            .collect()
+    );
+
+    // Different roles that grant the same permissions
+    let mut memory: InMemoryRbac<MyUser, MyRole, MyPermission> = InMemoryRbac::new();
+    memory.assign_role(&users[0], &roles[0]).unwrap();
+    memory.assign_role(&users[1], &roles[1]).unwrap();
+    memory
+        .add_permission(&roles[0], &MyPermission { id: 210 })
+        .unwrap();
+    memory
+        .add_permission(&roles[1], &MyPermission { id: 210 })
+        .unwrap();
+    assert_eq!(memory.duplicate_access_groups().len(), 1);
+}
+
+#[test]
+fn assign_roles() {
+    let (mut memory, users, roles, _) = test_environment();
+
+    // Some roles already exist
+    assert_eq!(memory.assign_roles(&users[2], &roles), Ok(2));
+    assert_eq!(memory.user_has_role(&users[2], &roles[3]), Ok(true));
+
+    // All roles already exist
+    assert_eq!(memory.assign_roles(&users[2], &roles[..2]), Ok(0));
+
+    // No roles
+    assert_eq!(memory.assign_roles(&users[4], &[]), Ok(0));
+    assert!(memory.iter_user_role_ids(&users[4]).is_err());
+
+    // An expired role is assigned again
+    memory
+        .assign_role_until(
+            &users[3],
+            &roles[1],
+            SystemTime::now() - Duration::from_secs(60),
+        )
+        .unwrap();
+    assert_eq!(memory.assign_roles(&users[3], &roles[1..2]), Ok(1));
+    assert_eq!(memory.user_has_role(&users[3], &roles[1]), Ok(true));
+
+    // A constraint is violated
+    memory.add_mutually_exclusive_roles(&roles[0], &roles[3]);
+    assert_eq!(
+        memory.assign_roles(&users[4], &roles[..]),
+        Err(InMemoryRbacError::ConstraintViolated(
+            ConstraintViolation::MutuallyExclusiveRole
+        ))
+    );
+    assert_eq!(memory.user_has_role(&users[4], &roles[2]), Ok(true));
+}
+
+#[test]
+fn builder_try_build() {
+    let (_, users, roles, permissions) = test_environment();
+
+    // No constraint is violated
+    let memory = RbacBuilder::new()
+        .assign(&users[2], &roles[0])
+        .assign(&users[2], &roles[1])
+        .grant(&roles[0], &permissions[0])
+        .mutually_exclusive_roles(&roles[0], &roles[2])
+        .try_build()
+        .unwrap();
+    assert_eq!(
+        memory.user_has_permission(&users[2], &permissions[0]),
+        Ok(true)
+    );
+
+    // Every violation is reported
+    let result = RbacBuilder::<MyUser, MyRole, MyPermission>::new()
+        .mutually_exclusive_roles(&roles[0], &roles[2])
+        .assign(&users[2], &roles[0])
+        .assign(&users[2], &roles[2])
+        .assign(&users[3], &roles[2])
+        .assign(&users[3], &roles[0])
+        .try_build();
+    assert_eq!(
+        result.err(),
+        Some(vec![
+            ConstraintViolation::MutuallyExclusiveRole,
+            ConstraintViolation::MutuallyExclusiveRole
+        ])
+    );
+}
+
+#[test]
+fn builder_build() {
+    let (memory, users, roles, permissions) = test_environment();
+
+    // The sample environment built through the builder
+    let mut builder = RbacBuilder::new()
+        .assign(&users[0], &roles[3])
+        .assign(&users[1], &roles[2])
+        .assign(&users[2], &roles[0])
+        .assign(&users[2], &roles[1])
+        .assign(&users[3], &roles[1])
+        .grant(&roles[0], &permissions[0])
+        .grant(&roles[0], &permissions[1])
+        .grant(&roles[1], &permissions[2]);
+    for permission in &permissions[..4] {
+        builder = builder.grant(&roles[2], permission);
+    }
+    for permission in &permissions {
+        builder = builder.grant(&roles[3], permission);
+    }
+    // Duplicates are ignored
+    let built = builder
+        .assign(&users[2], &roles[0])
+        .grant(&roles[1], &permissions[2])
+        .build();
+    assert_eq!(
+        built.access_equivalent(&memory, &users, &permissions),
+        Ok(true)
+    );
+    assert_eq!(built.total_assignments(), memory.total_assignments());
+    assert_eq!(built.fingerprint(), memory.fingerprint());
+}
+
+#[test]
+#[should_panic]
+fn builder_build_violation() {
+    let (_, users, roles, _) = test_environment();
+
+    RbacBuilder::<MyUser, MyRole, MyPermission>::new()
+        .mutually_exclusive_roles(&roles[0], &roles[2])
+        .assign(&users[2], &roles[0])
+        .assign(&users[2], &roles[2])
+        .build();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn model_serialize() {
+    let (memory, users, roles, permissions) = test_environment();
+
+    let json = serde_json::to_string(&memory).unwrap();
+    let restored: InMemoryRbac<MyUser, MyRole, MyPermission> = serde_json::from_str(&json).unwrap();
+
+    // All assignments and permissions survive the round trip
+    assert_eq!(restored.fingerprint(), memory.fingerprint());
+    for user in &users {
+        for role in &roles {
+            assert_eq!(
+                restored.user_has_role(user, role),
+                memory.user_has_role(user, role)
+            );
+        }
+    }
+    for role in &roles {
+        for permission in &permissions {
+            assert_eq!(
+                restored.role_has_permission(role, permission),
+                memory.role_has_permission(role, permission)
+            );
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn model_serialize_expiry() {
+    let (mut memory, users, roles, permissions) = test_environment();
+    memory
+        .assign_role_until(
+            &users[4],
+            &roles[0],
+            SystemTime::now() - Duration::from_secs(60),
+        )
+        .unwrap();
+    memory
+        .assign_role_until(
+            &users[3],
+            &roles[2],
+            SystemTime::now() + Duration::from_secs(60),
+        )
+        .unwrap();
+
+    let json = serde_json::to_string(&memory).unwrap();
+    let restored: InMemoryRbac<MyUser, MyRole, MyPermission> = serde_json::from_str(&json).unwrap();
+
+    // An expired assignment stays expired
+    assert_eq!(
+        memory.user_has_permission(&users[4], &permissions[0]),
+        Ok(false)
+    );
+    assert_eq!(
+        restored.user_has_permission(&users[4], &permissions[0]),
+        Ok(false)
+    );
+
+    // An assignment that hasn't expired yet still grants its permissions
+    assert_eq!(
+        restored.user_has_permission(&users[3], &permissions[3]),
+        Ok(true)
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn model_serialize_denied() {
+    let (mut memory, users, _, permissions) = test_environment();
+    memory.deny_permission(&users[2], &permissions[0]);
+
+    let json = serde_json::to_string(&memory).unwrap();
+    let restored: InMemoryRbac<MyUser, MyRole, MyPermission> = serde_json::from_str(&json).unwrap();
+
+    // The permission stays denied
+    assert_eq!(
+        restored.user_has_permission(&users[2], &permissions[0]),
+        Ok(false)
+    );
+    assert_eq!(
+        restored.user_has_permission(&users[2], &permissions[1]),
+        Ok(true)
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn model_serialize_resolution_depth() {
+    let (mut memory, _, _, permissions) = test_environment();
+    memory.set_max_resolution_depth(64);
+    for id in 300..340 {
+        memory
+            .add_role_parent(&MyRole { id }, &MyRole { id: id + 1 })
+            .unwrap();
+    }
+    memory
+        .add_permission(&MyRole { id: 340 }, &permissions[0])
+        .unwrap();
+
+    // A hierarchy deeper than the default limit still loads
+    let json = serde_json::to_string(&memory).unwrap();
+    let mut restored: InMemoryRbac<MyUser, MyRole, MyPermission> =
+        serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        restored.role_has_permission(&MyRole { id: 300 }, &permissions[0]),
+        Ok(true)
+    );
+
+    // The restored model keeps the configured limit
+    assert_eq!(
+        restored.add_role_parent(&MyRole { id: 340 }, &MyRole { id: 341 }),
+        Ok(true)
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn model_serialize_overrides() {
+    let (mut memory, users, _, permissions) = test_environment();
+    memory.emergency_override(&users[4], SystemTime::now() + Duration::from_secs(60));
+
+    let json = serde_json::to_string(&memory).unwrap();
+    let restored: InMemoryRbac<MyUser, MyRole, MyPermission> = serde_json::from_str(&json).unwrap();
+
+    // The override still grants every permission
+    assert_eq!(
+        restored.user_has_permission(&users[4], &permissions[0]),
+        Ok(true)
+    );
+    assert_eq!(
+        restored.user_has_permission(&users[3], &permissions[0]),
+        Ok(false)
+    );
+}
+
+#[test]
+fn clone() {
+    let (memory, users, roles, permissions) = test_environment();
+    let mut copy = memory.clone();
+
+    // Mutating the clone
+    copy.assign_role(&users[4], &roles[0]).unwrap();
+    copy.remove_permission(&roles[1], &permissions[2]).unwrap();
+    assert_eq!(copy.user_has_role(&users[4], &roles[0]), Ok(true));
+    assert_eq!(
+        copy.user_has_permission(&users[3], &permissions[2]),
+        Ok(false)
+    );
+
+    // The original is unchanged
+    assert_eq!(memory.user_has_role(&users[4], &roles[0]), Ok(false));
+    assert_eq!(
+        memory.user_has_permission(&users[3], &permissions[2]),
+        Ok(true)
+    );
+    assert_ne!(memory.fingerprint(), copy.fingerprint());
+}
+
+#[cfg(feature = "arc-swap")]
+#[test]
+fn rbac_handle_reload() {
+    let (memory, users, roles, _) = test_environment();
+    let handle = RbacHandle::new(memory);
+
+    // The initial model is active
+    let before = handle.load();
+    assert_eq!(before.user_has_role(&users[2], &roles[0]), Ok(true));
+
+    // sam moves from agent and salesperson to supervisor
+    let reload = || {
+        let (mut memory, users, roles, _) = test_environment();
+        memory.unassign_role(&users[2], &roles[0]).unwrap();
+        memory.unassign_role(&users[2], &roles[1]).unwrap();
+        memory.assign_role(&users[2], &roles[2]).unwrap();
+        memory
+    };
+
+    // Readers only ever see either the old or the new roles of sam
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..1000 {
+                    let snapshot = handle.load();
+                    let agent = snapshot.user_has_role(&users[2], &roles[0]).unwrap();
+                    let salesperson = snapshot.user_has_role(&users[2], &roles[1]).unwrap();
+                    let supervisor = snapshot.user_has_role(&users[2], &roles[2]).unwrap();
+                    assert_eq!(agent, salesperson);
+                    assert_ne!(agent, supervisor);
+                }
+            });
+        }
+        for _ in 0..20 {
+            handle.reload(reload);
+        }
+    });
+
+    // The new model is active, but the old snapshot is unchanged
+    assert_eq!(handle.load().user_has_role(&users[2], &roles[2]), Ok(true));
+    assert_eq!(before.user_has_role(&users[2], &roles[0]), Ok(true));
+}
+
+#[test]
+fn debug() {
+    let (memory, _, _, _) = test_environment();
+
+    let output = format!("{:?}", memory);
+    assert!(output.starts_with("InMemoryRbac {"));
+
+    // legolas only has salesperson, which only has generate_form
+    assert!(output.contains("13: {111}"));
+    assert!(output.contains("111: {212}"));
+}
+
+#[test]
+fn counts() {
+    let (mut memory, users, roles, permissions) = test_environment();
+
+    // frodo has no role and isn't counted
+    assert_eq!(memory.user_count(), 4);
+    assert_eq!(memory.role_count(), 4);
+    assert_eq!(memory.total_assignments(), 5);
+
+    // A role without permissions isn't counted
+    memory.assign_role(&users[4], &MyRole { id: 116 }).unwrap();
+    assert_eq!(memory.user_count(), 5);
+    assert_eq!(memory.role_count(), 4);
+    assert_eq!(memory.total_assignments(), 6);
+
+    // A role loses its only permission
+    memory
+        .remove_permission(&roles[1], &permissions[2])
+        .unwrap();
+    assert_eq!(memory.role_count(), 3);
+}
+
+#[test]
+fn clear() {
+    let (mut memory, users, roles, permissions) = test_environment();
+
+    // Only the assignments are removed
+    let mut users_cleared = memory.clone();
+    users_cleared.clear_users();
+    assert_eq!(users_cleared.user_count(), 0);
+    assert_eq!(
+        users_cleared.role_has_permission(&roles[1], &permissions[2]),
+        Ok(true)
+    );
+
+    // Only the permissions are removed
+    let mut permissions_cleared = memory.clone();
+    permissions_cleared.clear_permissions();
+    assert_eq!(permissions_cleared.role_count(), 0);
+    assert_eq!(
+        permissions_cleared.user_has_role(&users[3], &roles[1]),
+        Ok(true)
+    );
+
+    // Everything is removed
+    memory.enable_audit(100);
+    memory.clear();
+    for user in &users {
+        for permission in &permissions {
+            assert_eq!(memory.user_has_permission(user, permission), Ok(false));
+        }
+    }
+    assert_eq!(memory.audit_for_user(&users[2]).len(), 2);
+}
+
+#[test]
+fn with_capacity() {
+    let mut memory: InMemoryRbac<MyUser, MyRole, MyPermission> = InMemoryRbac::with_capacity(10, 5);
+    let user = MyUser { id: 10 };
+    let role = MyRole { id: 110 };
+    let permission = MyPermission { id: 210 };
+
+    // The model starts empty
+    assert_eq!(memory.user_count(), 0);
+    assert_eq!(memory.user_has_permission(&user, &permission), Ok(false));
+
+    // The model works like any other
+    memory.assign_role(&user, &role).unwrap();
+    memory.add_permission(&role, &permission).unwrap();
+    assert_eq!(memory.user_has_permission(&user, &permission), Ok(true));
+}
+
+#[test]
+fn is_empty() {
+    let mut memory: InMemoryRbac<MyUser, MyRole, MyPermission> = InMemoryRbac::new();
+    assert!(memory.is_empty());
+
+    // An assignment alone makes the model non-empty
+    memory
+        .assign_role(&MyUser { id: 10 }, &MyRole { id: 110 })
+        .unwrap();
+    assert!(!memory.is_empty());
+
+    memory.clear();
+    assert!(memory.is_empty());
+}
+
+#[test]
+fn contains() {
+    let (mut memory, users, roles, _) = test_environment();
+
+    // frodo has no roles and isn't known
+    assert!(memory.contains_user(&users[2]));
+    assert!(!memory.contains_user(&users[4]));
+    memory.assign_role(&users[4], &roles[0]).unwrap();
+    assert!(memory.contains_user(&users[4]));
+    memory.unassign_role(&users[4], &roles[0]).unwrap();
+    assert!(!memory.contains_user(&users[4]));
+
+    // Only roles with permissions are defined
+    assert!(memory.contains_role_definition(&roles[1]));
+    assert!(!memory.contains_role_definition(&MyRole { id: 114 }));
+}
+
+#[test]
+fn shrink_to_fit() {
+    let mut memory: InMemoryRbac<MyUser, MyRole, MyPermission> = InMemoryRbac::new();
+    let role = MyRole { id: 110 };
+    let users: Vec<MyUser> = (0..1000).map(|id| MyUser { id }).collect();
+    for user in &users {
+        memory.assign_role(user, &role).unwrap();
+    }
+    memory
+        .add_permission(&role, &MyPermission { id: 210 })
+        .unwrap();
+    let (before, _) = memory.capacity();
+
+    for user in &users[1..] {
+        memory.unassign_role(user, &role).unwrap();
+    }
+
+    // Shrinking reduces the capacity
+    memory.shrink_to_fit();
+    assert!(memory.capacity().0 < before);
+    assert_eq!(memory.user_count(), 1);
+    assert_eq!(
+        memory.user_has_permission(&users[0], &MyPermission { id: 210 }),
+        Ok(true)
+    );
+}
+
+#[test]
+fn partial_eq() {
+    let (memory, users, roles, permissions) = test_environment();
+
+    // The same model built in a different order
+    let mut other: InMemoryRbac<MyUser, MyRole, MyPermission> = InMemoryRbac::new();
+    for permission in permissions.iter().rev() {
+        other.add_permission(&roles[3], permission).unwrap();
+    }
+    for permission in permissions[..4].iter().rev() {
+        other.add_permission(&roles[2], permission).unwrap();
+    }
+    other.add_permission(&roles[1], &permissions[2]).unwrap();
+    other.add_permission(&roles[0], &permissions[1]).unwrap();
+    other.add_permission(&roles[0], &permissions[0]).unwrap();
+    other.assign_role(&users[3], &roles[1]).unwrap();
+    other.assign_role(&users[2], &roles[1]).unwrap();
+    other.assign_role(&users[2], &roles[0]).unwrap();
+    other.assign_role(&users[1], &roles[2]).unwrap();
+    other.assign_role(&users[0], &roles[3]).unwrap();
+    assert!(memory == other);
+
+    // A denied permission and an expiry time
+    let mut denied = memory.clone();
+    denied.deny_permission(&users[2], &permissions[0]);
+    assert!(memory != denied);
+    let mut expiring = memory.clone();
+    expiring
+        .assign_role_until(
+            &users[2],
+            &roles[0],
+            SystemTime::now() + Duration::from_secs(60),
+        )
+        .unwrap();
+    assert!(memory != expiring);
+
+    // An extra permission
+    other.add_permission(&roles[1], &permissions[4]).unwrap();
+    assert!(memory != other);
+}
+
+#[test]
+fn custom_hasher() {
+    type FixedState = BuildHasherDefault<DefaultHasher>;
+
+    let mut memory: InMemoryRbac<MyUser, MyRole, MyPermission, FixedState> =
+        InMemoryRbac::with_hasher(FixedState::default());
+    let user = MyUser { id: 10 };
+    let role = MyRole { id: 110 };
+    let permission = MyPermission { id: 210 };
+
+    // The model works like one with the default hasher
+    memory.assign_role(&user, &role).unwrap();
+    memory.add_permission(&role, &permission).unwrap();
+    assert_eq!(memory.user_has_permission(&user, &permission), Ok(true));
+    assert_eq!(
+        memory.user_has_permission(&user, &MyPermission { id: 211 }),
+        Ok(false)
+    );
+}
+
+#[test]
+fn permission_groups() {
+    let (mut memory, users, roles, permissions) = test_environment();
+    let full_access = MyPermission { id: 220 };
+    let sales = MyPermission { id: 221 };
+
+    // A nested group expands to the members of both groups
+    memory
+        .define_permission_group(&sales, &[MyPermission { id: 212 }])
+        .unwrap();
+    memory
+        .define_permission_group(
+            &full_access,
+            &[
+                MyPermission { id: 213 },
+                MyPermission { id: 214 },
+                MyPermission { id: 221 },
+            ],
+        )
+        .unwrap();
+    memory.add_permission(&roles[0], &full_access).unwrap();
+    for permission in &permissions {
+        assert_eq!(memory.user_has_permission(&users[2], permission), Ok(true));
+    }
+    assert_eq!(memory.user_has_permission(&users[2], &sales), Ok(true));
+    assert_eq!(
+        memory.role_has_permission(&roles[0], &permissions[4]),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.iter_user_permission_ids(&users[2]).unwrap().count(),
+        7
+    );
+
+    // Other roles don't have the group
+    assert_eq!(
+        memory.user_has_permission(&users[3], &permissions[3]),
+        Ok(false)
+    );
+
+    // A group can't contain itself
+    assert_eq!(
+        memory.define_permission_group(&sales, &[MyPermission { id: 220 }]),
+        Err(InMemoryRbacError::PermissionGroupCycle)
+    );
+    assert_eq!(
+        memory.define_permission_group(&sales, &[MyPermission { id: 221 }]),
+        Err(InMemoryRbacError::PermissionGroupCycle)
+    );
+
+    // A group without members is removed
+    memory.define_permission_group(&full_access, &[]).unwrap();
+    assert_eq!(
+        memory.user_has_permission(&users[2], &permissions[3]),
+        Ok(false)
+    );
+}
+
+#[test]
+fn max_resolution_depth() {
+    let (mut memory, _, roles, _) = test_environment();
+    memory.set_max_resolution_depth(2);
+
+    // A hierarchy up to the limit is fine
+    assert_eq!(memory.add_role_parent(&roles[0], &roles[1]), Ok(true));
+    assert_eq!(memory.add_role_parent(&roles[1], &roles[2]), Ok(true));
+
+    // A chain deeper than the limit is rejected, no matter at which end it grows
+    assert_eq!(
+        memory.add_role_parent(&roles[2], &roles[3]),
+        Err(InMemoryRbacError::ResolutionDepthExceeded)
+    );
+    assert_eq!(
+        memory.add_role_parent(&MyRole { id: 114 }, &roles[0]),
+        Err(InMemoryRbacError::ResolutionDepthExceeded)
+    );
+    assert_eq!(memory.add_role_parent(&roles[3], &roles[2]), Ok(true));
+
+    // Permission groups are limited as well
+    memory.set_max_resolution_depth(DEFAULT_MAX_RESOLUTION_DEPTH);
+    for id in 300..332 {
+        memory
+            .define_permission_group(&MyPermission { id }, &[MyPermission { id: id + 1 }])
+            .unwrap();
+    }
+    assert_eq!(
+        memory.define_permission_group(&MyPermission { id: 332 }, &[MyPermission { id: 333 }]),
+        Err(InMemoryRbacError::ResolutionDepthExceeded)
+    );
+    assert_eq!(
+        memory.define_permission_group(&MyPermission { id: 299 }, &[MyPermission { id: 300 }]),
+        Err(InMemoryRbacError::ResolutionDepthExceeded)
+    );
+}
+
+#[test]
+fn transfer_roles() {
+    let (mut memory, users, roles, permissions) = test_environment();
+
+    // sam's agent role is new to legolas, salesperson isn't
+    assert_eq!(memory.transfer_roles(&users[2], &users[3]), Ok(1));
+    assert_eq!(memory.user_has_role(&users[3], &roles[0]), Ok(true));
+    assert_eq!(memory.user_has_role(&users[3], &roles[1]), Ok(true));
+    assert_eq!(memory.iter_user_role_ids(&users[3]).unwrap().count(), 2);
+    assert_eq!(
+        memory.user_has_permission(&users[3], &permissions[0]),
+        Ok(true)
+    );
+
+    // sam has no roles anymore
+    assert!(!memory.contains_user(&users[2]));
+    assert_eq!(memory.transfer_roles(&users[2], &users[3]), Ok(0));
+
+    // A constraint is violated
+    memory.add_mutually_exclusive_roles(&roles[0], &roles[3]);
+    assert_eq!(
+        memory.transfer_roles(&users[3], &users[0]),
+        Err(InMemoryRbacError::ConstraintViolated(
+            ConstraintViolation::MutuallyExclusiveRole
+        ))
+    );
+    assert!(memory.contains_user(&users[3]));
+}
+
+#[test]
+fn rename_role() {
+    let (mut memory, users, roles, permissions) = test_environment();
+    let call_agent = MyRole { id: 120 };
+
+    // agent is renamed to a fresh id
+    assert_eq!(memory.rename_role(&roles[0], &call_agent), Ok(true));
+    assert_eq!(memory.user_has_role(&users[2], &roles[0]), Ok(false));
+    assert_eq!(memory.user_has_role(&users[2], &call_agent), Ok(true));
+    assert_eq!(
+        memory.user_has_permission(&users[2], &permissions[0]),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.role_has_permission(&call_agent, &permissions[1]),
+        Ok(true)
+    );
+    assert!(!memory.contains_role_definition(&roles[0]));
+
+    // The role doesn't exist anymore
+    assert_eq!(memory.rename_role(&roles[0], &call_agent), Ok(false));
+
+    // Renaming to an existing role merges both
+    assert_eq!(memory.rename_role(&call_agent, &roles[1]), Ok(true));
+    assert_eq!(memory.iter_user_role_ids(&users[2]).unwrap().count(), 1);
+    assert_eq!(
+        memory.iter_role_permission_ids(&roles[1]).unwrap().count(),
+        3
+    );
+    assert_eq!(
+        memory.user_has_permission(&users[3], &permissions[0]),
+        Ok(true)
+    );
+
+    // A role can't be merged into its parent
+    memory.add_role_parent(&roles[1], &roles[2]).unwrap();
+    assert_eq!(
+        memory.rename_role(&roles[1], &roles[2]),
+        Err(InMemoryRbacError::RoleHierarchyCycle)
+    );
+}
+
+#[test]
+fn remove_permission_everywhere() {
+    let (mut memory, users, roles, permissions) = test_environment();
+
+    // make_calls is granted by agent, supervisor and administrator
+    assert_eq!(memory.remove_permission_everywhere(&permissions[0]), 3);
+    for user in &users {
+        assert_eq!(memory.user_has_permission(user, &permissions[0]), Ok(false));
+    }
+    assert_eq!(
+        memory.role_has_permission(&roles[0], &permissions[1]),
+        Ok(true)
+    );
+
+    // Roles left without permissions are removed
+    assert_eq!(memory.remove_permission_everywhere(&permissions[2]), 3);
+    assert!(!memory.contains_role_definition(&roles[1]));
+
+    // No role has the permission anymore
+    assert_eq!(memory.remove_permission_everywhere(&permissions[0]), 0);
+}
+
+#[test]
+fn clear_user_roles() {
+    let (mut memory, users, roles, permissions) = test_environment();
+
+    // The user had roles
+    assert_eq!(memory.clear_user_roles(&users[2]), Ok(true));
+    assert_eq!(
+        memory.user_has_permission(&users[2], &permissions[0]),
+        Ok(false)
+    );
+    assert_eq!(
+        memory.iter_user_role_ids(&users[2]).unwrap_err(),
+        InMemoryRbacError::UserHasNoRoles
+    );
+
+    // Other users and the roles themselves are untouched
+    assert_eq!(memory.user_has_role(&users[3], &roles[1]), Ok(true));
+    assert_eq!(
+        memory.role_has_permission(&roles[0], &permissions[0]),
+        Ok(true)
+    );
+
+    // The user has no roles anymore
+    assert_eq!(memory.clear_user_roles(&users[2]), Ok(false));
+    assert_eq!(memory.clear_user_roles(&users[4]), Ok(false));
+}
+
+#[test]
+fn user_permissions_check() {
+    let (memory, users, _, permissions) = test_environment();
+
+    // The results are in the order of the permissions
+    assert_eq!(
+        memory.user_permissions_check(&users[2], &permissions),
+        Ok(vec![true, true, true, false, false])
+    );
+    assert_eq!(
+        memory.user_permissions_check(&users[4], &permissions),
+        Ok(vec![false; 5])
+    );
+
+    // No permissions to check
+    assert_eq!(memory.user_permissions_check(&users[2], &[]), Ok(vec![]));
+}
+
+#[test]
+fn user_has_any_and_all_permissions() {
+    let (memory, users, _, permissions) = test_environment();
+
+    // sam has some, but not all permissions
+    assert_eq!(
+        memory.user_has_any_permission(&users[2], &permissions),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.user_has_all_permissions(&users[2], &permissions),
+        Ok(false)
+    );
+
+    // gandalf has all permissions, frodo none
+    assert_eq!(
+        memory.user_has_all_permissions(&users[0], &permissions),
+        Ok(true)
+    );
+    assert_eq!(
+        memory.user_has_any_permission(&users[4], &permissions),
+        Ok(false)
+    );
+
+    // No permissions to check
+    assert_eq!(memory.user_has_any_permission(&users[0], &[]), Ok(false));
+    assert_eq!(memory.user_has_all_permissions(&users[4], &[]), Ok(true));
+}
+
+#[test]
+fn first_missing_permission() {
+    let (memory, users, _, _) = test_environment();
+    let required = [
+        MyPermission { id: 210 },
+        MyPermission { id: 213 },
+        MyPermission { id: 211 },
+    ];
+
+    // sam lacks only the second permission
+    assert_eq!(
+        memory.first_missing_permission(&users[2], &required),
+        Ok(Some(213))
+    );
+
+    // frodo has none of the permissions
+    assert_eq!(
+        memory.first_missing_permission(&users[4], &required),
+        Ok(Some(210))
+    );
+
+    // elrond and gandalf have all of the permissions
+    assert_eq!(
+        memory.first_missing_permission(&users[1], &required),
+        Ok(None)
+    );
+    assert_eq!(
+        memory.first_missing_permission(&users[0], &required),
+        Ok(None)
+    );
+    assert_eq!(memory.first_missing_permission(&users[4], &[]), Ok(None));
+}
+
+#[test]
+fn user_has_any_and_all_permissions_short_circuit() {
+    struct CountedPermission {
+        id: u32,
+        checked: Cell<bool>,
+    }
+
+    impl Identifiable for CountedPermission {
+        type Id = u32;
+
+        fn get_rbac_id(&self) -> Self::Id {
+            self.checked.set(true);
+            self.id
+        }
+    }
+
+    let counted = |id| CountedPermission {
+        id,
+        checked: Cell::new(false),
+    };
+    let user = MyUser { id: 10 };
+    let role = MyRole { id: 110 };
+    let mut memory: InMemoryRbac<MyUser, MyRole, CountedPermission> = InMemoryRbac::new();
+    memory.assign_role(&user, &role).unwrap();
+    memory.add_permission(&role, &counted(210)).unwrap();
+
+    // any stops at the first permission the user has
+    let permissions = [counted(211), counted(210), counted(212)];
+    assert_eq!(
+        memory.user_has_any_permission(&user, &permissions),
+        Ok(true)
+    );
+    assert!(permissions[1].checked.get());
+    assert!(!permissions[2].checked.get());
+
+    // all stops at the first permission the user doesn't have
+    let permissions = [counted(210), counted(211), counted(212)];
+    assert_eq!(
+        memory.user_has_all_permissions(&user, &permissions),
+        Ok(false)
+    );
+    assert!(permissions[1].checked.get());
+    assert!(!permissions[2].checked.get());
+}
+
+#[test]
+fn shared_rbac() {
+    let (memory, users, roles, permissions) = test_environment();
+    let shared = SharedRbac::new(memory);
+
+    // Checks run while another thread assigns roles
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            let shared = shared.clone();
+            let (users, permissions) = (&users, &permissions);
+            scope.spawn(move || {
+                for _ in 0..1000 {
+                    assert_eq!(
+                        shared.user_has_permission(&users[2], &permissions[0]),
+                        Ok(true)
+                    );
+                    shared
+                        .user_has_permission(&users[4], &permissions[3])
+                        .unwrap();
+                }
+            });
+        }
+        let shared = shared.clone();
+        let (users, roles) = (&users, &roles);
+        scope.spawn(move || {
+            for _ in 0..100 {
+                shared.assign_role(&users[4], &roles[2]).unwrap();
+                shared.unassign_role(&users[4], &roles[2]).unwrap();
+            }
+            shared.assign_role(&users[4], &roles[2]).unwrap();
+        });
+    });
+
+    // All handles see the last change
+    assert_eq!(
+        shared.user_has_permission(&users[4], &permissions[3]),
+        Ok(true)
+    );
+    assert_eq!(shared.read().user_count(), 5);
+}
+
+#[cfg(feature = "async")]
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+    loop {
+        if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
+
+/// A future that is pending once before resolving, like a query to a database.
+#[cfg(feature = "async")]
+struct Query<T>(Option<T>, bool);
+
+#[cfg(feature = "async")]
+impl<T: Unpin> std::future::Future for Query<T> {
+    type Output = T;
+
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        context: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<T> {
+        if !self.1 {
+            self.1 = true;
+            context.waker().wake_by_ref();
+            return std::task::Poll::Pending;
+        }
+        std::task::Poll::Ready(self.0.take().unwrap())
+    }
+}
+
+#[cfg(feature = "async")]
+struct DatabaseRbac {
+    user_roles: HashSet<(u32, u32)>,
+    role_permissions: HashSet<(u32, u32)>,
+}
+
+#[cfg(feature = "async")]
+impl traits::AsyncRbacModel<MyUser, MyRole, MyPermission> for DatabaseRbac {
+    type Error = ();
+
+    async fn assign_role(&mut self, user: &MyUser, role: &MyRole) -> Result<bool, ()> {
+        let inserted = self.user_roles.insert((user.id, role.id));
+        Query(Some(Ok(inserted)), false).await
+    }
+
+    async fn unassign_role(&mut self, user: &MyUser, role: &MyRole) -> Result<bool, ()> {
+        let removed = self.user_roles.remove(&(user.id, role.id));
+        Query(Some(Ok(removed)), false).await
+    }
+
+    async fn add_permission(
+        &mut self,
+        role: &MyRole,
+        permission: &MyPermission,
+    ) -> Result<bool, ()> {
+        let inserted = self.role_permissions.insert((role.id, permission.id));
+        Query(Some(Ok(inserted)), false).await
+    }
+
+    async fn remove_permission(
+        &mut self,
+        role: &MyRole,
+        permission: &MyPermission,
+    ) -> Result<bool, ()> {
+        let removed = self.role_permissions.remove(&(role.id, permission.id));
+        Query(Some(Ok(removed)), false).await
+    }
+
+    async fn user_has_role(&self, user: &MyUser, role: &MyRole) -> Result<bool, ()> {
+        let found = self.user_roles.contains(&(user.id, role.id));
+        Query(Some(Ok(found)), false).await
+    }
+
+    async fn role_has_permission(
+        &self,
+        role: &MyRole,
+        permission: &MyPermission,
+    ) -> Result<bool, ()> {
+        let found = self.role_permissions.contains(&(role.id, permission.id));
+        Query(Some(Ok(found)), false).await
+    }
+
+    async fn user_has_permission(
+        &self,
+        user: &MyUser,
+        permission: &MyPermission,
+    ) -> Result<bool, ()> {
+        let found = self
+            .user_roles
+            .iter()
+            .any(|&(u, r)| u == user.id && self.role_permissions.contains(&(r, permission.id)));
+        Query(Some(Ok(found)), false).await
+    }
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn async_rbac_model() {
+    use rbac::traits::AsyncRbacModel;
+
+    let (mut memory, users, roles, permissions) = test_environment();
+    let mut database = DatabaseRbac {
+        user_roles: HashSet::new(),
+        role_permissions: HashSet::new(),
+    };
+
+    // The database store resolves its queries asynchronously
+    assert_eq!(
+        block_on(database.assign_role(&users[2], &roles[0])),
+        Ok(true)
+    );
+    assert_eq!(
+        block_on(database.add_permission(&roles[0], &permissions[0])),
+        Ok(true)
+    );
+    assert_eq!(
+        block_on(database.user_has_permission(&users[2], &permissions[0])),
+        Ok(true)
+    );
+    assert_eq!(
+        block_on(database.user_has_permission(&users[2], &permissions[1])),
+        Ok(false)
+    );
+
+    // The in-memory model behaves like its synchronous counterpart
+    assert_eq!(
+        block_on(AsyncRbacModel::user_has_permission(
+            &memory,
+            &users[2],
+            &permissions[0]
+        )),
+        Ok(true)
+    );
+    assert_eq!(
+        block_on(AsyncRbacModel::assign_role(
+            &mut memory,
+            &users[4],
+            &roles[2]
+        )),
+        Ok(true)
+    );
+    assert_eq!(
+        block_on(AsyncRbacModel::user_has_role(&memory, &users[4], &roles[2])),
+        Ok(true)
+    );
+}
+
+#[derive(Debug, PartialEq)]
+enum StoreError {
+    NotFound,
+    Disconnected,
+}
+
+impl traits::NotFound for StoreError {
+    fn is_not_found(&self) -> bool {
+        *self == StoreError::NotFound
+    }
+}
+
+/// A store whose connection to the database can be lost.
+struct RemoteStore {
+    connected: bool,
+    user_roles: HashMap<u32, Vec<u32>>,
+    role_permissions: HashMap<u32, Vec<u32>>,
+}
+
+impl RemoteStore {
+    fn query<'a>(&self, map: &'a HashMap<u32, Vec<u32>>, id: u32) -> Result<&'a [u32], StoreError> {
+        if !self.connected {
+            return Err(StoreError::Disconnected);
+        }
+        map.get(&id).map(Vec::as_slice).ok_or(StoreError::NotFound)
+    }
+}
+
+impl<'a> RbacIterators<MyUser, MyRole, MyPermission> for &'a RemoteStore {
+    type Error = StoreError;
+    type UserRoles = std::iter::Copied<std::slice::Iter<'a, u32>>;
+    type RolePermissions = std::iter::Copied<std::slice::Iter<'a, u32>>;
+
+    fn iter_user_role_ids(self, user: &MyUser) -> Result<Self::UserRoles, Self::Error> {
+        Ok(self.query(&self.user_roles, user.id)?.iter().copied())
+    }
+
+    fn iter_role_permission_ids(self, role: &MyRole) -> Result<Self::RolePermissions, Self::Error> {
+        Ok(self.query(&self.role_permissions, role.id)?.iter().copied())
+    }
+}
+
+impl RbacModel<MyUser, MyRole, MyPermission> for RemoteStore {
+    type Error = StoreError;
+
+    fn assign_role(&mut self, _: &MyUser, _: &MyRole) -> Result<bool, StoreError> {
+        Err(StoreError::Disconnected)
+    }
+
+    fn unassign_role(&mut self, _: &MyUser, _: &MyRole) -> Result<bool, StoreError> {
+        Err(StoreError::Disconnected)
+    }
+
+    fn add_permission(&mut self, _: &MyRole, _: &MyPermission) -> Result<bool, StoreError> {
+        Err(StoreError::Disconnected)
+    }
+
+    fn remove_permission(&mut self, _: &MyRole, _: &MyPermission) -> Result<bool, StoreError> {
+        Err(StoreError::Disconnected)
+    }
+
+    fn user_has_permission(&self, _: &MyUser, _: &MyPermission) -> Result<bool, StoreError> {
+        Err(StoreError::Disconnected)
+    }
+}
+
+#[test]
+fn default_checks_propagate_errors() {
+    let (_, users, roles, permissions) = test_environment();
+    let mut store = RemoteStore {
+        connected: true,
+        user_roles: vec![(12, vec![110])].into_iter().collect(),
+        role_permissions: vec![(110, vec![210])].into_iter().collect(),
+    };
+
+    // Found
+    assert_eq!(store.user_has_role(&users[2], &roles[0]), Ok(true));
+    assert_eq!(
+        store.role_has_permission(&roles[0], &permissions[0]),
+        Ok(true)
+    );
+
+    // Not found
+    assert_eq!(store.user_has_role(&users[4], &roles[0]), Ok(false));
+    assert_eq!(
+        store.role_has_permission(&roles[1], &permissions[0]),
+        Ok(false)
+    );
+
+    // The connection is lost
+    store.connected = false;
+    assert_eq!(
+        store.user_has_role(&users[2], &roles[0]),
+        Err(StoreError::Disconnected)
+    );
+    assert_eq!(
+        store.role_has_permission(&roles[0], &permissions[0]),
+        Err(StoreError::Disconnected)
+    );
+}
+
+#[test]
+fn is_not_found() {
+    use rbac::traits::NotFound;
+
+    assert!(InMemoryRbacError::UserHasNoRoles.is_not_found());
+    assert!(InMemoryRbacError::RoleHasNoPermissions.is_not_found());
+    assert!(!InMemoryRbacError::PermissionLimitExceeded.is_not_found());
+}
+
+#[derive(Default)]
+struct CountingObserver {
+    assigned: Cell<usize>,
+    unassigned: Cell<usize>,
+    added: Cell<usize>,
+    removed: Cell<usize>,
+}
+
+impl RbacObserver<u32, u32, u32> for CountingObserver {
+    fn on_assign_role(&self, _: &u32, _: &u32) {
+        self.assigned.set(self.assigned.get() + 1);
+    }
+
+    fn on_unassign_role(&self, _: &u32, _: &u32) {
+        self.unassigned.set(self.unassigned.get() + 1);
+    }
+
+    fn on_add_permission(&self, _: &u32, _: &u32) {
+        self.added.set(self.added.get() + 1);
+    }
+
+    fn on_remove_permission(&self, _: &u32, _: &u32) {
+        self.removed.set(self.removed.get() + 1);
+    }
+}
+
+#[test]
+fn observed_rbac() {
+    let (memory, users, roles, permissions) = test_environment();
+    let mut observed = ObservedRbac::new(memory, CountingObserver::default());
+
+    // Changes are reported
+    observed.assign_role(&users[4], &roles[0]).unwrap();
+    observed.unassign_role(&users[2], &roles[1]).unwrap();
+    observed.add_permission(&roles[1], &permissions[4]).unwrap();
+    observed
+        .remove_permission(&roles[0], &permissions[1])
+        .unwrap();
+
+    // Calls without an effect aren't
+    observed.assign_role(&users[4], &roles[0]).unwrap();
+    observed.unassign_role(&users[2], &roles[1]).unwrap();
+    observed.add_permission(&roles[1], &permissions[4]).unwrap();
+    observed
+        .remove_permission(&roles[0], &permissions[1])
+        .unwrap();
+
+    let observer = observed.observer();
+    assert_eq!(observer.assigned.get(), 1);
+    assert_eq!(observer.unassigned.get(), 1);
+    assert_eq!(observer.added.get(), 1);
+    assert_eq!(observer.removed.get(), 1);
+    assert_eq!(
+        observed.user_has_permission(&users[4], &permissions[0]),
+        Ok(true)
+    );
+}
+
+#[test]
+fn btree_rbac() {
+    let mut memory: BTreeRbac<MyUser, MyRole, MyPermission> = BTreeRbac::new();
+    let user = MyUser { id: 10 };
+    for id in [113, 110, 112, 111] {
+        memory.assign_role(&user, &MyRole { id }).unwrap();
+    }
+    memory
+        .add_permission(&MyRole { id: 112 }, &MyPermission { id: 212 })
+        .unwrap();
+
+    // The roles of the user come out sorted
+    assert_eq!(
+        memory
+            .iter_user_role_ids(&user)
+            .unwrap()
+            .collect::<Vec<_>>(),
+        vec![110, 111, 112, 113]
+    );
+    assert_eq!(
+        memory.user_has_permission(&user, &MyPermission { id: 212 }),
+        Ok(true)
+    );
+
+    // A user whose last role is unassigned has no roles
+    memory.unassign_role(&user, &MyRole { id: 110 }).unwrap();
+    assert_eq!(memory.iter_user_role_ids(&user).unwrap().count(), 3);
+    for id in 111..114 {
+        memory.unassign_role(&user, &MyRole { id }).unwrap();
+    }
+    assert_eq!(
+        memory.iter_user_role_ids(&user).err(),
+        Some(InMemoryRbacError::UserHasNoRoles)
+    );
+    assert_eq!(
+        memory.user_has_permission(&user, &MyPermission { id: 212 }),
+        Ok(false)
+    );
+}
+
+#[test]
+fn to_dot() {
+    let mut memory: InMemoryRbac<String, String, String> = InMemoryRbac::new();
+    let sam = "sam".to_string();
+    let agent = "agent".to_string();
+    let supervisor = "supervisor".to_string();
+    memory.assign_role(&sam, &agent).unwrap();
+    memory
+        .add_permission(&agent, &"make_calls".to_string())
+        .unwrap();
+    memory.add_role_parent(&supervisor, &agent).unwrap();
+    memory
+        .define_permission_group(&"say \"hi\"".to_string(), &["make_calls".to_string()])
+        .unwrap();
+
+    let dot = memory.to_dot();
+    assert!(dot.starts_with("digraph rbac {\n"));
+    assert!(dot.ends_with("}\n"));
+    assert!(dot.contains("    \"user:sam\" [label=\"sam\", shape=ellipse, color=blue];\n"));
+    assert!(dot.contains("    \"user:sam\" -> \"role:agent\";\n"));
+    assert!(dot.contains("    \"role:agent\" -> \"permission:make_calls\";\n"));
+    assert!(dot.contains("    \"role:supervisor\" -> \"role:agent\" [style=dashed];\n"));
+
+    // Quotes in ids are escaped
+    assert!(dot.contains(
+        "    \"permission:say \\\"hi\\\"\" -> \"permission:make_calls\" [style=dotted];\n"
+    ));
+}